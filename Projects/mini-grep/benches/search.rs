@@ -0,0 +1,69 @@
+// Compares the line-by-line `search` with the whole-buffer `search_fast`.
+//
+// Run with `cargo bench`. The corpus is generated, so the numbers only depend
+// on the machine: about 32 MiB of short lines, with a rare needle on one line
+// in ten thousand, a common one on most lines, and one that never occurs.
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use mini_grep::{Match, search, search_fast};
+
+const WORDS: &[&str] = &[
+    "the", "quick", "brown", "fox", "jumps", "over", "lazy", "dog", "and", "runs", "into", "a",
+    "forest", "where", "nobody", "has", "ever", "seen", "it", "again",
+];
+const SIZE: usize = 32 << 20;
+const RUNS: usize = 5;
+
+fn corpus() -> String {
+    let mut text = String::with_capacity(SIZE + 100);
+    let mut seed: u64 = 1;
+    let mut line = 0;
+    while text.len() < SIZE {
+        line += 1;
+        if line % 10_000 == 0 {
+            text.push_str("Sherlock ");
+        }
+        for _ in 0..8 {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            text.push_str(WORDS[(seed >> 33) as usize % WORDS.len()]);
+            text.push(' ');
+        }
+        text.push('\n');
+    }
+    text
+}
+
+// The fastest of a few runs, and what the last one returned.
+fn time<'a>(f: impl Fn() -> Vec<Match<'a>>) -> (Duration, usize) {
+    let mut best = Duration::MAX;
+    let mut found = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        found = black_box(f()).len();
+        best = best.min(start.elapsed());
+    }
+    (best, found)
+}
+
+fn main() {
+    let text = corpus();
+    let mib = text.len() as f64 / (1 << 20) as f64;
+    println!("{mib:.0} MiB, best of {RUNS} runs");
+    println!(
+        "{:<10} {:>9} {:>14} {:>14} {:>8}",
+        "needle", "lines", "search", "search_fast", "speedup"
+    );
+    for needle in ["Sherlock", "fox", "Moriarty"] {
+        let (slow, slow_found) = time(|| search(needle, &text));
+        let (fast, fast_found) = time(|| search_fast(needle, &text));
+        assert_eq!(slow_found, fast_found, "{needle}");
+        let rate = |d: Duration| format!("{:.0} MiB/s", mib / d.as_secs_f64());
+        println!(
+            "{needle:<10} {slow_found:>9} {:>14} {:>14} {:>7.1}x",
+            rate(slow),
+            rate(fast),
+            slow.as_secs_f64() / fast.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,283 @@
+// Multi-pattern literal search with the Aho-Corasick algorithm.
+//
+// All patterns go into one trie whose failure links let a single pass over
+// the line find every occurrence of every pattern, instead of searching the
+// line once per pattern. The automaton works on characters so the same code
+// serves case-insensitive search: patterns and text are both case folded
+// (see `fold`), and positions are mapped back to the original text.
+use std::cell::RefCell;
+use std::ops::Range;
+
+use crate::fold::fold;
+
+thread_local! {
+    // For the last folded characters of the text being searched, as many
+    // as the longest pattern has: the byte offset of the original character
+    // each came from, and whether it is the first of its folding. Kept from
+    // one search to the next, so searching a line allocates nothing.
+    static ORIGINS: RefCell<Vec<(usize, bool)>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Debug, Clone, Default)]
+struct State {
+    // transitions, sorted by character
+    next: Vec<(char, usize)>,
+    fail: usize,
+    // length of the prefix this state stands for, in folded characters
+    depth: usize,
+    // lengths (in folded characters) of the patterns that end here,
+    // including those reached through failure links
+    out: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AhoCorasick {
+    states: Vec<State>,
+    case_insensitive: bool,
+    // an empty pattern matches at every position
+    has_empty: bool,
+    // the length of the longest pattern, in folded characters
+    longest: usize,
+}
+
+impl AhoCorasick {
+    pub fn new<P: AsRef<str>>(patterns: &[P], case_insensitive: bool) -> AhoCorasick {
+        let mut ac = AhoCorasick {
+            states: vec![State::default()],
+            case_insensitive,
+            has_empty: false,
+            longest: 0,
+        };
+        for pattern in patterns {
+            let mut state = 0;
+            let mut len = 0;
+            for c in pattern.as_ref().chars() {
+                for f in ac.fold(c) {
+                    state = ac.goto_or_insert(state, f);
+                    len += 1;
+                }
+            }
+            ac.longest = ac.longest.max(len);
+            if len == 0 {
+                ac.has_empty = true;
+            } else if !ac.states[state].out.contains(&len) {
+                ac.states[state].out.push(len);
+            }
+        }
+        ac.build_failure_links();
+        ac
+    }
+
+    fn fold(&self, c: char) -> impl Iterator<Item = char> + use<> {
+        let (folded, same) = if self.case_insensitive {
+            (Some(fold(c)), None)
+        } else {
+            (None, Some(c))
+        };
+        folded.into_iter().flatten().chain(same)
+    }
+
+    fn goto(&self, state: usize, c: char) -> Option<usize> {
+        let next = &self.states[state].next;
+        next.binary_search_by_key(&c, |&(k, _)| k)
+            .ok()
+            .map(|i| next[i].1)
+    }
+
+    fn goto_or_insert(&mut self, state: usize, c: char) -> usize {
+        let next = &self.states[state].next;
+        match next.binary_search_by_key(&c, |&(k, _)| k) {
+            Ok(i) => next[i].1,
+            Err(i) => {
+                let id = self.states.len();
+                let depth = self.states[state].depth + 1;
+                self.states.push(State {
+                    depth,
+                    ..State::default()
+                });
+                self.states[state].next.insert(i, (c, id));
+                id
+            }
+        }
+    }
+
+    // Breadth-first, so a state's failure target is always finished first.
+    fn build_failure_links(&mut self) {
+        let mut queue: Vec<usize> = self.states[0].next.iter().map(|&(_, s)| s).collect();
+        let mut head = 0;
+        while head < queue.len() {
+            let state = queue[head];
+            head += 1;
+            for (c, child) in self.states[state].next.clone() {
+                let mut fail = self.states[state].fail;
+                let target = loop {
+                    if let Some(target) = self.goto(fail, c) {
+                        break target;
+                    }
+                    if fail == 0 {
+                        break 0;
+                    }
+                    fail = self.states[fail].fail;
+                };
+                self.states[child].fail = target;
+                let inherited = self.states[target].out.clone();
+                self.states[child].out.extend(inherited);
+                queue.push(child);
+            }
+        }
+    }
+
+    fn step(&self, mut state: usize, c: char) -> usize {
+        loop {
+            if let Some(next) = self.goto(state, c) {
+                return next;
+            }
+            if state == 0 {
+                return 0;
+            }
+            state = self.states[state].fail;
+        }
+    }
+
+    // Byte range of the leftmost match in `text`; when several patterns
+    // start at the same position the longest one wins.
+    pub fn find(&self, text: &str) -> Option<Range<usize>> {
+        self.find_where(text, |_| true)
+    }
+
+    // Like `find`, among the matches that `accept` takes. A shorter pattern
+    // can still be taken when a longer one at the same position is not, and
+    // an empty pattern only when nothing longer is.
+    pub fn find_where(
+        &self,
+        text: &str,
+        accept: impl Fn(&Range<usize>) -> bool,
+    ) -> Option<Range<usize>> {
+        ORIGINS.with_borrow_mut(|origins| {
+            let window = self.longest.max(1);
+            origins.clear();
+            origins.resize(window, (0, false));
+            // the folded character `n` characters into the text is at
+            // `origins[n % window]`
+            let origin = |origins: &[(usize, bool)], n: usize| origins[n % window];
+            let mut folded_count = 0;
+            let mut best: Option<Range<usize>> = None;
+            let consider = |candidate: Range<usize>, best: &mut Option<Range<usize>>| {
+                let better = match best {
+                    None => true,
+                    Some(b) => {
+                        candidate.start < b.start
+                            || (candidate.start == b.start && candidate.end > b.end)
+                    }
+                };
+                if better && accept(&candidate) {
+                    *best = Some(candidate);
+                }
+            };
+            let mut state = 0;
+            for (i, c) in text.char_indices() {
+                if self.has_empty {
+                    consider(i..i, &mut best);
+                }
+                // no character folds to more than three
+                let mut folded = ['\0'; 3];
+                let mut n = 0;
+                for f in self.fold(c) {
+                    folded[n] = f;
+                    n += 1;
+                }
+                for (k, &f) in folded[..n].iter().enumerate() {
+                    origins[folded_count % window] = (i, k == 0);
+                    folded_count += 1;
+                    state = self.step(state, f);
+                    // a match may only end where an original character ends
+                    if k + 1 < n {
+                        continue;
+                    }
+                    for &len in &self.states[state].out {
+                        let (start, whole) = origin(origins, folded_count - len);
+                        if whole {
+                            consider(start..i + c.len_utf8(), &mut best);
+                        }
+                    }
+                }
+                // stop once no partial match in progress starts at or before
+                // the best match, since nothing found later could beat it
+                if let Some(b) = &best {
+                    let depth = self.states[state].depth;
+                    if depth == 0 || origin(origins, folded_count - depth).0 > b.start {
+                        return best;
+                    }
+                }
+            }
+            if self.has_empty {
+                consider(text.len()..text.len(), &mut best);
+            }
+            best
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find<'a>(patterns: &[&str], text: &'a str, case_insensitive: bool) -> Option<&'a str> {
+        let ac = AhoCorasick::new(patterns, case_insensitive);
+        ac.find(text).map(|span| &text[span])
+    }
+
+    #[test]
+    fn finds_leftmost_of_many_patterns() {
+        let patterns = ["he", "she", "his", "hers"];
+        assert_eq!(Some("she"), find(&patterns, "ushers", false));
+        assert_eq!(Some("his"), find(&patterns, "this", false));
+        assert_eq!(None, find(&patterns, "nothing", false));
+    }
+
+    #[test]
+    fn prefers_longest_at_the_same_start() {
+        assert_eq!(Some("hers"), find(&["he", "hers"], "hers", false));
+        assert_eq!(Some("abcd"), find(&["bcd", "abcd", "ab"], "xabcd", false));
+    }
+
+    #[test]
+    fn rejected_matches_leave_room_for_others() {
+        let ac = AhoCorasick::new(&["a", "a-b", "bc"], false);
+        let span = ac.find_where("a-bc", |span| span.end != 3);
+        assert_eq!(Some(0..1), span);
+        assert_eq!(Some(2..4), ac.find_where("a-bc", |span| span.start > 0));
+        assert_eq!(None, ac.find_where("a-bc", |_| false));
+    }
+
+    #[test]
+    fn failure_links_find_overlapping_patterns() {
+        assert_eq!(Some("aab"), find(&["aab", "ab"], "aaab", false));
+        assert_eq!(Some("bc"), find(&["abcd", "bc"], "abce", false));
+    }
+
+    #[test]
+    fn case_insensitive_patterns() {
+        assert_eq!(Some("RUST"), find(&["rust", "go"], "I like RUST", true));
+        assert_eq!(Some("STRASSE"), find(&["straße"], "die STRASSE", true));
+        assert_eq!(None, find(&["s"], "ß", true));
+        assert_eq!(None, find(&["rust"], "I like RUST", false));
+    }
+
+    #[test]
+    fn empty_pattern_matches_everything() {
+        assert_eq!(Some(""), find(&["x", ""], "abc", false));
+        // but a longer pattern at the same position wins
+        assert_eq!(Some("foo"), find(&["", "foo"], "foo bar", false));
+        let ac = AhoCorasick::new(&["", "foo"], false);
+        assert_eq!(Some(1..4), ac.find_where("xfoo", |span| span.start > 0));
+        assert_eq!(Some(2..2), ac.find_where("ab", |span| span.start > 1));
+    }
+
+    #[test]
+    fn long_lines_are_searched_in_a_window() {
+        let text = format!("{}hers", "x".repeat(10_000));
+        assert_eq!(Some("hers"), find(&["he", "hers"], &text, false));
+        assert_eq!(Some("STRASSE"), find(&["straße"], "die STRASSE", true));
+    }
+}
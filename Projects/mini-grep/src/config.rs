@@ -0,0 +1,1040 @@
+// Command-line configuration: the flag table, the parser and the usage text.
+//
+// Defaults can be kept in a config file, `$MINIGREP_CONFIG` or else
+// `~/.config/minigrep/config`, holding one argument per line. Settings are
+// applied from that file first, then from IGNORE_CASE and USE_REGEX, then
+// from the command line, so each overrides the one before.
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::GrepError;
+
+// One command-line option. `value` names the argument the option takes, or
+// is `None` for a plain switch.
+pub struct Flag {
+    pub short: Option<char>,
+    pub long: &'static str,
+    pub value: Option<&'static str>,
+    pub help: &'static str,
+}
+
+// Every option mini-grep understands. The parser and `usage` both read from
+// this table, so adding an option here is enough to document it.
+pub const FLAGS: &[Flag] = &[
+    Flag {
+        short: Some('e'),
+        long: "regexp",
+        value: Some("PATTERN"),
+        help: "Search for PATTERN; may be given more than once",
+    },
+    Flag {
+        short: Some('f'),
+        long: "file",
+        value: Some("FILE"),
+        help: "Search for the patterns in FILE, one per line",
+    },
+    Flag {
+        short: Some('i'),
+        long: "ignore-case",
+        value: None,
+        help: "Search case-insensitively (also enabled by IGNORE_CASE)",
+    },
+    Flag {
+        short: Some('s'),
+        long: "case-sensitive",
+        value: None,
+        help: "Search case-sensitively, overriding IGNORE_CASE",
+    },
+    Flag {
+        short: Some('E'),
+        long: "regex",
+        value: None,
+        help: "Treat PATTERN as a regular expression (also enabled by USE_REGEX)",
+    },
+    Flag {
+        short: Some('F'),
+        long: "fixed-strings",
+        value: None,
+        help: "Treat PATTERN as a literal string, overriding USE_REGEX",
+    },
+    Flag {
+        short: Some('a'),
+        long: "text",
+        value: None,
+        help: "Search binary files as if they were text",
+    },
+    Flag {
+        short: Some('z'),
+        long: "search-zip",
+        value: None,
+        help: "Decompress gzip-compressed files before searching them",
+    },
+    Flag {
+        short: Some('w'),
+        long: "word-regexp",
+        value: None,
+        help: "Select only matches that form whole words",
+    },
+    Flag {
+        short: Some('x'),
+        long: "line-regexp",
+        value: None,
+        help: "Select only matches that form whole lines",
+    },
+    Flag {
+        short: Some('U'),
+        long: "multiline",
+        value: None,
+        help: "Let matches span lines, and print every line a match covers",
+    },
+    Flag {
+        short: None,
+        long: "fuzzy",
+        value: Some("N"),
+        help: "Allow up to N edits in each match and print how many it has",
+    },
+    Flag {
+        short: Some('n'),
+        long: "line-number",
+        value: None,
+        help: "Prefix each matching line with its line number",
+    },
+    Flag {
+        short: None,
+        long: "column",
+        value: None,
+        help: "Prefix each matching line with its line and column number",
+    },
+    Flag {
+        short: Some('H'),
+        long: "with-filename",
+        value: None,
+        help: "Prefix each matching line with its file path",
+    },
+    Flag {
+        short: Some('A'),
+        long: "after-context",
+        value: Some("NUM"),
+        help: "Print NUM lines of context after each match",
+    },
+    Flag {
+        short: Some('B'),
+        long: "before-context",
+        value: Some("NUM"),
+        help: "Print NUM lines of context before each match",
+    },
+    Flag {
+        short: Some('C'),
+        long: "context",
+        value: Some("NUM"),
+        help: "Print NUM lines of context before and after each match",
+    },
+    Flag {
+        short: None,
+        long: "color",
+        value: Some("WHEN"),
+        help: "Highlight matches: auto (the default), always or never",
+    },
+    Flag {
+        short: None,
+        long: "replace",
+        value: Some("TEXT"),
+        help: "Print matching lines with every match replaced by TEXT",
+    },
+    Flag {
+        short: None,
+        long: "write",
+        value: None,
+        help: "With --replace, rewrite the files in place instead of printing",
+    },
+    Flag {
+        short: None,
+        long: "dry-run",
+        value: None,
+        help: "With --write, print a diff of the changes instead of making them",
+    },
+    Flag {
+        short: None,
+        long: "json",
+        value: None,
+        help: "Print results as JSON Lines, one object per match",
+    },
+    Flag {
+        short: Some('c'),
+        long: "count",
+        value: None,
+        help: "Print only the number of matching lines per file",
+    },
+    Flag {
+        short: Some('l'),
+        long: "files-with-matches",
+        value: None,
+        help: "Print only the paths of files containing a match",
+    },
+    Flag {
+        short: Some('L'),
+        long: "files-without-match",
+        value: None,
+        help: "Print only the paths of files containing no match",
+    },
+    Flag {
+        short: Some('q'),
+        long: "quiet",
+        value: None,
+        help: "Print nothing; exit with status 0 on the first match",
+    },
+    Flag {
+        short: Some('v'),
+        long: "invert-match",
+        value: None,
+        help: "Select lines that do not match PATTERN",
+    },
+    Flag {
+        short: Some('r'),
+        long: "recursive",
+        value: None,
+        help: "Search the current directory instead of stdin when no FILE is given",
+    },
+    Flag {
+        short: None,
+        long: "include",
+        value: Some("GLOB"),
+        help: "Search only files matching GLOB in directories; may be repeated",
+    },
+    Flag {
+        short: None,
+        long: "exclude",
+        value: Some("GLOB"),
+        help: "Skip files and directories matching GLOB; may be repeated",
+    },
+    Flag {
+        short: None,
+        long: "hidden",
+        value: None,
+        help: "Also search hidden files and directories",
+    },
+    Flag {
+        short: None,
+        long: "follow",
+        value: None,
+        help: "Keep searching the files as lines are appended to them",
+    },
+    Flag {
+        short: None,
+        long: "interactive",
+        value: None,
+        help: "Refine the query in a terminal UI and open results in $EDITOR",
+    },
+    Flag {
+        short: Some('j'),
+        long: "threads",
+        value: Some("NUM"),
+        help: "Search up to NUM files in parallel (default: one per CPU)",
+    },
+    Flag {
+        short: None,
+        long: "help",
+        value: None,
+        help: "Print this help message and exit",
+    },
+    Flag {
+        short: None,
+        long: "version",
+        value: None,
+        help: "Print version information and exit",
+    },
+    Flag {
+        short: None,
+        long: "no-config",
+        value: None,
+        help: "Ignore the config file (MINIGREP_CONFIG or ~/.config/minigrep/config)",
+    },
+];
+
+// The options that `--no-NAME` turns back off, so that what the config file
+// set can be undone on the command line.
+const NEGATABLE: &[&str] = &[
+    "ignore-case",
+    "regex",
+    "text",
+    "search-zip",
+    "word-regexp",
+    "line-regexp",
+    "multiline",
+    "fuzzy",
+    "line-number",
+    "column",
+    "with-filename",
+    "context",
+    "json",
+    "replace",
+    "write",
+    "dry-run",
+    "count",
+    "files-with-matches",
+    "files-without-match",
+    "quiet",
+    "invert-match",
+    "recursive",
+    "include",
+    "exclude",
+    "hidden",
+    "follow",
+    "interactive",
+];
+
+// What is printed for the selected lines of each file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputMode {
+    // every selected line (with context, if requested)
+    #[default]
+    Lines,
+    // the number of selected lines per file
+    Count,
+    // the path of every file with at least one selected line
+    FilesWithMatches,
+    // the path of every file without any selected line
+    FilesWithoutMatch,
+    // nothing; only whether anything was selected
+    Quiet,
+}
+
+impl OutputMode {
+    // Modes that are decided by the first selected line of a file.
+    pub fn stops_at_first_match(self) -> bool {
+        matches!(
+            self,
+            OutputMode::FilesWithMatches | OutputMode::FilesWithoutMatch | OutputMode::Quiet
+        )
+    }
+}
+
+// Whether output is colored with ANSI escape sequences.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    // only when writing to a terminal and NO_COLOR is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                is_terminal && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    // the patterns a line is searched for; it is selected if any matches
+    pub patterns: Vec<String>,
+    // files holding more patterns, one per line, read when the search starts
+    pub pattern_files: Vec<String>,
+    pub file_paths: Vec<String>,
+    pub ignore_case: bool,
+    pub use_regex: bool,
+    // print the lines of binary files instead of "Binary file ... matches"
+    pub text: bool,
+    // search gzip files (-z) by decompressing them on the fly
+    pub decompress: bool,
+    // matches must be whole words (-w) or whole lines (-x; wins over -w)
+    pub word_regexp: bool,
+    pub line_regexp: bool,
+    // -U: search whole files at once, so matches may span lines
+    pub multiline: bool,
+    // with --fuzzy, the most edits a match may be away from a pattern
+    pub fuzzy: Option<usize>,
+    pub line_number: bool,
+    pub column: bool,
+    pub with_filename: bool,
+    pub before_context: usize,
+    pub after_context: usize,
+    pub output: OutputMode,
+    pub color: ColorChoice,
+    pub json: bool,
+    // the text matches are replaced with, and whether files are rewritten
+    pub replace: Option<String>,
+    pub write: bool,
+    pub dry_run: bool,
+    pub invert_match: bool,
+    pub recursive: bool,
+    // globs filtering the files found in directories
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub hidden: bool,
+    // --follow: poll the files for appended lines until killed
+    pub follow: bool,
+    // --interactive: the files are searched from a terminal UI, and any
+    // PATTERN given with -e is only where the query starts
+    pub interactive: bool,
+    // number of files searched in parallel; 0 picks one per CPU
+    pub threads: usize,
+    // --no-config: the config file is not read
+    pub no_config: bool,
+    pub help: bool,
+    pub version: bool,
+}
+
+impl Config {
+    pub fn build(args: &[String]) -> Result<Config, GrepError> {
+        let args = args.get(1..).unwrap_or_default();
+        // the command line is parsed once on its own to learn whether it has
+        // --no-config, as the config file has to be applied before it
+        let mut command_line = Config::default();
+        command_line.parse_flags(args).map_err(GrepError::Usage)?;
+        let mut config = Config::default();
+        if !command_line.no_config
+            && let Some((path, contents)) = read_config_file()?
+        {
+            config
+                .parse_config_file(&contents)
+                .map_err(|e| GrepError::Usage(format!("{}: {e}", path.display())))?;
+        }
+        // feat: add env for case sensitive
+        if env::var("IGNORE_CASE").is_ok() {
+            config.ignore_case = true;
+        }
+        // feat: treat the query as a regular expression
+        if env::var("USE_REGEX").is_ok() {
+            config.use_regex = true;
+        }
+        // flags are applied after the environment so they always win
+        config.parse_args(args).map_err(GrepError::Usage)?;
+        Ok(config)
+    }
+
+    // Applies the flags of a config file: one argument per line, with blank
+    // lines and lines starting with '#' skipped. Patterns and files can only
+    // be given on the command line.
+    fn parse_config_file(&mut self, contents: &str) -> Result<(), String> {
+        let args: Vec<String> = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from)
+            .collect();
+        if let Some(arg) = self.parse_flags(&args)?.first() {
+            return Err(format!("'{arg}' is not an option"));
+        }
+        if !self.patterns.is_empty() || !self.pattern_files.is_empty() {
+            return Err(String::from("patterns cannot be set in the config file"));
+        }
+        Ok(())
+    }
+
+    fn parse_args(&mut self, args: &[String]) -> Result<(), String> {
+        let positional = self.parse_flags(args)?;
+        self.finish(positional)
+    }
+
+    // Applies every option in `args` and returns the other arguments.
+    fn parse_flags(&mut self, args: &[String]) -> Result<Vec<String>, String> {
+        let mut positional = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                positional.extend(args.by_ref().cloned());
+            } else if let Some(long) = arg.strip_prefix("--") {
+                let (name, inline) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None => (long, None),
+                };
+                let Some(flag) = FLAGS.iter().find(|flag| flag.long == name) else {
+                    match name.strip_prefix("no-") {
+                        Some(negated) if NEGATABLE.contains(&negated) && inline.is_none() => {
+                            self.unset(negated);
+                            continue;
+                        }
+                        _ => return Err(format!("unknown option '--{name}'")),
+                    }
+                };
+                let value = take_value(flag, inline, &mut args)?;
+                self.apply(flag, value)?;
+            } else if arg.len() > 1 && arg.starts_with('-') {
+                // short options may be bundled, as in `-in`
+                let shorts = &arg[1..];
+                for (i, c) in shorts.char_indices() {
+                    let flag = FLAGS
+                        .iter()
+                        .find(|flag| flag.short == Some(c))
+                        .ok_or_else(|| format!("unknown option '-{c}'"))?;
+                    if flag.value.is_some() {
+                        let rest = &shorts[i + c.len_utf8()..];
+                        let inline = (!rest.is_empty()).then(|| rest.to_string());
+                        let value = take_value(flag, inline, &mut args)?;
+                        self.apply(flag, value)?;
+                        break;
+                    }
+                    self.apply(flag, None)?;
+                }
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+        Ok(positional)
+    }
+
+    // Takes the pattern and files from the positional arguments and checks
+    // that the options make sense together.
+    fn finish(&mut self, positional: Vec<String>) -> Result<(), String> {
+        if self.help || self.version {
+            return Ok(());
+        }
+        if self.interactive {
+            self.interactive_files(positional)?;
+        } else {
+            let mut positional = positional.into_iter();
+            // without -e or -f the first positional argument is the pattern
+            if self.patterns.is_empty() && self.pattern_files.is_empty() {
+                let pattern = positional
+                    .next()
+                    .ok_or_else(|| format!("no PATTERN given\n\n{}", usage()))?;
+                self.patterns.push(pattern);
+            }
+            self.file_paths = positional.collect();
+            // with no FILE, search stdin (or the current directory with -r)
+            if self.file_paths.is_empty() {
+                let default = if self.recursive { "." } else { "-" };
+                self.file_paths.push(String::from(default));
+            }
+        }
+        // JSON records describe lines; counts and file lists have no form there
+        if self.json && !matches!(self.output, OutputMode::Lines | OutputMode::Quiet) {
+            return Err(String::from("--json cannot be combined with -c, -l or -L"));
+        }
+        if self.fuzzy.is_some() && self.use_regex {
+            return Err(String::from("--fuzzy cannot be combined with -E"));
+        }
+        if self.write && self.replace.is_none() {
+            return Err(String::from("--write needs --replace"));
+        }
+        if self.dry_run && !self.write {
+            return Err(String::from("--dry-run needs --write"));
+        }
+        if self.write && (self.json || self.invert_match || self.decompress || self.multiline) {
+            return Err(String::from(
+                "--write cannot be combined with --json, -v, -z or -U",
+            ));
+        }
+        if self.interactive
+            && (self.json || self.output != OutputMode::Lines || self.replace.is_some())
+        {
+            return Err(String::from(
+                "--interactive cannot be combined with --json, -c, -l, -L, -q or --replace",
+            ));
+        }
+        // following never reaches the end that counts and whole files need
+        if self.follow
+            && (self.output != OutputMode::Lines
+                || self.decompress
+                || self.multiline
+                || self.write
+                || self.interactive)
+        {
+            return Err(String::from(
+                "--follow cannot be combined with -c, -l, -L, -q, -z, -U, --write or --interactive",
+            ));
+        }
+        if self.follow && self.file_paths.iter().any(|p| p == "-") {
+            return Err(String::from("--follow needs a FILE, not standard input"));
+        }
+        if self.write && self.file_paths.iter().any(|p| p == "-") {
+            return Err(String::from("--write cannot rewrite standard input"));
+        }
+        Ok(())
+    }
+
+    // The query is typed in the UI, so every positional argument is a file,
+    // and the current directory is searched when there are none.
+    fn interactive_files(&mut self, positional: Vec<String>) -> Result<(), String> {
+        self.file_paths = positional;
+        if self.file_paths.is_empty() {
+            self.file_paths.push(String::from("."));
+        }
+        if self.patterns.len() > 1 || !self.pattern_files.is_empty() {
+            return Err(String::from(
+                "--interactive takes at most one -e PATTERN and no -f",
+            ));
+        }
+        if self.file_paths.iter().any(|p| p == "-") {
+            return Err(String::from("--interactive cannot search standard input"));
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, flag: &Flag, value: Option<String>) -> Result<(), String> {
+        let number = || -> Result<usize, String> {
+            let value = value.as_deref().unwrap_or_default();
+            value
+                .parse()
+                .map_err(|_| format!("invalid {} argument '{value}'", flag.long))
+        };
+        match flag.long {
+            "regexp" => self.patterns.push(value.unwrap_or_default()),
+            "file" => self.pattern_files.push(value.unwrap_or_default()),
+            "ignore-case" => self.ignore_case = true,
+            "case-sensitive" => self.ignore_case = false,
+            "regex" => self.use_regex = true,
+            "fixed-strings" => self.use_regex = false,
+            "text" => self.text = true,
+            "search-zip" => self.decompress = true,
+            "word-regexp" => self.word_regexp = true,
+            "line-regexp" => self.line_regexp = true,
+            "multiline" => self.multiline = true,
+            "fuzzy" => self.fuzzy = Some(number()?),
+            "line-number" => self.line_number = true,
+            "column" => self.column = true,
+            "with-filename" => self.with_filename = true,
+            "after-context" => self.after_context = number()?,
+            "before-context" => self.before_context = number()?,
+            "context" => {
+                self.before_context = number()?;
+                self.after_context = self.before_context;
+            }
+            "color" => {
+                self.color = match value.as_deref() {
+                    Some("auto") => ColorChoice::Auto,
+                    Some("always") => ColorChoice::Always,
+                    Some("never") => ColorChoice::Never,
+                    other => {
+                        let other = other.unwrap_or_default();
+                        return Err(format!("invalid color argument '{other}'"));
+                    }
+                };
+            }
+            "json" => self.json = true,
+            "replace" => self.replace = value,
+            "write" => self.write = true,
+            "dry-run" => self.dry_run = true,
+            "count" => self.output = OutputMode::Count,
+            "files-with-matches" => self.output = OutputMode::FilesWithMatches,
+            "files-without-match" => self.output = OutputMode::FilesWithoutMatch,
+            "quiet" => self.output = OutputMode::Quiet,
+            "invert-match" => self.invert_match = true,
+            "recursive" => self.recursive = true,
+            "include" => self.include.push(value.unwrap_or_default()),
+            "exclude" => self.exclude.push(value.unwrap_or_default()),
+            "hidden" => self.hidden = true,
+            "follow" => self.follow = true,
+            "interactive" => self.interactive = true,
+            "threads" => {
+                self.threads = number()?;
+                if self.threads == 0 {
+                    return Err(String::from("threads must be at least 1"));
+                }
+            }
+            "help" => self.help = true,
+            "version" => self.version = true,
+            "no-config" => self.no_config = true,
+            other => return Err(format!("option '--{other}' is not implemented")),
+        }
+        Ok(())
+    }
+
+    // Undoes an option for `--no-NAME`; `name` is one of `NEGATABLE`.
+    fn unset(&mut self, name: &str) {
+        match name {
+            "ignore-case" => self.ignore_case = false,
+            "regex" => self.use_regex = false,
+            "text" => self.text = false,
+            "search-zip" => self.decompress = false,
+            "word-regexp" => self.word_regexp = false,
+            "line-regexp" => self.line_regexp = false,
+            "multiline" => self.multiline = false,
+            "fuzzy" => self.fuzzy = None,
+            "line-number" => self.line_number = false,
+            "column" => self.column = false,
+            "with-filename" => self.with_filename = false,
+            "context" => {
+                self.before_context = 0;
+                self.after_context = 0;
+            }
+            "json" => self.json = false,
+            "replace" => self.replace = None,
+            "write" => self.write = false,
+            "dry-run" => self.dry_run = false,
+            // only the output mode in effect is turned off
+            "count" if self.output == OutputMode::Count => self.output = OutputMode::Lines,
+            "files-with-matches" if self.output == OutputMode::FilesWithMatches => {
+                self.output = OutputMode::Lines
+            }
+            "files-without-match" if self.output == OutputMode::FilesWithoutMatch => {
+                self.output = OutputMode::Lines
+            }
+            "quiet" if self.output == OutputMode::Quiet => self.output = OutputMode::Lines,
+            "invert-match" => self.invert_match = false,
+            "recursive" => self.recursive = false,
+            "include" => self.include.clear(),
+            "exclude" => self.exclude.clear(),
+            "hidden" => self.hidden = false,
+            "follow" => self.follow = false,
+            "interactive" => self.interactive = false,
+            _ => {}
+        }
+    }
+}
+
+// The path and contents of the config file, if there is one. A file named
+// by MINIGREP_CONFIG has to exist; the default one is optional.
+fn read_config_file() -> Result<Option<(PathBuf, String)>, GrepError> {
+    let (path, required) = match env::var_os("MINIGREP_CONFIG") {
+        Some(path) if !path.is_empty() => (PathBuf::from(path), true),
+        _ => {
+            let dir = match env::var_os("XDG_CONFIG_HOME") {
+                Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+                _ => match env::var_os("HOME") {
+                    Some(home) => PathBuf::from(home).join(".config"),
+                    None => return Ok(None),
+                },
+            };
+            (dir.join("minigrep").join("config"), false)
+        }
+    };
+    match fs::read_to_string(&path) {
+        Ok(contents) => Ok(Some((path, contents))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound && !required => Ok(None),
+        Err(e) => Err(GrepError::io(path, e)),
+    }
+}
+
+// Returns the option's argument, taken from `--flag=value`, `-fvalue` or the
+// next command-line argument.
+fn take_value<'a>(
+    flag: &Flag,
+    inline: Option<String>,
+    rest: &mut impl Iterator<Item = &'a String>,
+) -> Result<Option<String>, String> {
+    match (flag.value, inline) {
+        (None, None) => Ok(None),
+        (None, Some(_)) => Err(format!("option '--{}' does not take a value", flag.long)),
+        (Some(_), Some(value)) => Ok(Some(value)),
+        (Some(name), None) => rest
+            .next()
+            .cloned()
+            .map(Some)
+            .ok_or_else(|| format!("option '--{}' requires a {name} argument", flag.long)),
+    }
+}
+
+// The help text, generated from `FLAGS`.
+pub fn usage() -> String {
+    let specs: Vec<String> = FLAGS
+        .iter()
+        .map(|flag| {
+            let short = match flag.short {
+                Some(c) => format!("-{c}, "),
+                None => String::from("    "),
+            };
+            match flag.value {
+                Some(value) => format!("{short}--{}={value}", flag.long),
+                None => format!("{short}--{}", flag.long),
+            }
+        })
+        .collect();
+    let width = specs.iter().map(String::len).max().unwrap_or(0);
+
+    let mut text = String::from(
+        "Usage: mini-grep [OPTIONS] PATTERN [FILE]...\n\
+         \x20  or: mini-grep [OPTIONS] -e PATTERN... [-f FILE]... [FILE]...\n\
+         \x20  or: mini-grep --interactive [OPTIONS] [-e PATTERN] [FILE]...\n\
+         Search for PATTERN in each FILE. Directories are searched recursively.\n\
+         With no FILE, or when FILE is -, read standard input.\n\n\
+         Options:\n",
+    );
+    for (spec, flag) in specs.iter().zip(FLAGS) {
+        text.push_str(&format!("  {spec:width$}  {}\n", flag.help));
+    }
+    text.push_str("\nThese can be turned off again with --no-NAME, as in --no-line-number:\n ");
+    let mut column = 1;
+    for (i, name) in NEGATABLE.iter().enumerate() {
+        let sep = if i + 1 < NEGATABLE.len() { "," } else { "\n" };
+        if column + 1 + name.len() + sep.len() > 78 {
+            text.push_str("\n ");
+            column = 1;
+        }
+        text.push_str(&format!(" {name}{sep}"));
+        column += 1 + name.len() + sep.len();
+    }
+    text
+}
+
+pub fn version() -> String {
+    format!("mini-grep {}", env!("CARGO_PKG_VERSION"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, String> {
+        let mut config = Config::default();
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        config.parse_args(&args).map(|_| config)
+    }
+
+    #[test]
+    fn positional_pattern_and_files() {
+        let config = parse(&["duct", "a.txt", "b.txt"]).unwrap();
+        assert_eq!(vec!["duct"], config.patterns);
+        assert_eq!(vec!["a.txt", "b.txt"], config.file_paths);
+    }
+
+    #[test]
+    fn multiple_patterns() {
+        let config = parse(&["-e", "foo", "--regexp=bar", "-fpats.txt", "a.txt"]).unwrap();
+        assert_eq!(vec!["foo", "bar"], config.patterns);
+        assert_eq!(vec!["pats.txt"], config.pattern_files);
+        // with -e every positional argument is a file
+        assert_eq!(vec!["a.txt"], config.file_paths);
+    }
+
+    #[test]
+    fn short_long_and_bundled_flags() {
+        let config = parse(&["-in", "--count", "duct", "a.txt", "-v"]).unwrap();
+        assert!(config.ignore_case && config.line_number && config.invert_match);
+        assert_eq!(OutputMode::Count, config.output);
+        assert!(!config.use_regex);
+    }
+
+    #[test]
+    fn flags_override_environment_defaults() {
+        let mut config = Config {
+            ignore_case: true,
+            use_regex: true,
+            ..Config::default()
+        };
+        let args: Vec<String> = ["-s", "-F", "duct", "a.txt"]
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        config.parse_args(&args).unwrap();
+        assert!(!config.ignore_case && !config.use_regex);
+    }
+
+    #[test]
+    fn config_file_sets_defaults() {
+        let mut config = Config::default();
+        config
+            .parse_config_file("# defaults\n--line-number\n\n  -C\n2\n--color=never\n")
+            .unwrap();
+        let args: Vec<String> = ["-A", "0", "duct", "a.txt"]
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        config.parse_args(&args).unwrap();
+        assert!(config.line_number);
+        assert_eq!((2, 0), (config.before_context, config.after_context));
+        assert_eq!(ColorChoice::Never, config.color);
+        assert_eq!(vec!["duct"], config.patterns);
+
+        let mut config = Config::default();
+        let err = config.parse_config_file("-n\nduct\n").unwrap_err();
+        assert_eq!("'duct' is not an option", err);
+        let err = config.parse_config_file("-e\nduct\n").unwrap_err();
+        assert_eq!("patterns cannot be set in the config file", err);
+        assert!(config.parse_config_file("--bogus").is_err());
+    }
+
+    #[test]
+    fn negated_flags_undo_the_config_file() {
+        let mut config = Config::default();
+        config
+            .parse_config_file("--line-number\n--json\n--hidden\n-C\n2\n--exclude=target\n-c\n")
+            .unwrap();
+        let args: Vec<String> = [
+            "--no-line-number",
+            "--no-json",
+            "--no-hidden",
+            "--no-context",
+            "--no-exclude",
+            "--no-files-with-matches",
+            "duct",
+        ]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+        config.parse_args(&args).unwrap();
+        assert!(!config.line_number && !config.json && !config.hidden);
+        assert_eq!((0, 0), (config.before_context, config.after_context));
+        assert!(config.exclude.is_empty());
+        // -c was not turned off by the --no- of another output mode
+        assert_eq!(OutputMode::Count, config.output);
+        assert_eq!(
+            OutputMode::Lines,
+            parse(&["-c", "--no-count", "duct"]).unwrap().output
+        );
+
+        assert!(parse(&["--no-threads=2", "duct"]).is_err());
+        assert!(parse(&["--no-json=yes", "duct"]).is_err());
+    }
+
+    #[test]
+    fn no_config_is_found_by_the_parser() {
+        assert!(parse(&["--no-config", "duct"]).unwrap().no_config);
+        // an option's value is not an option
+        let config = parse(&["-e", "--no-config", "a.txt"]).unwrap();
+        assert!(!config.no_config);
+        assert_eq!(vec!["--no-config"], config.patterns);
+        assert!(!parse(&["--", "--no-config"]).unwrap().no_config);
+    }
+
+    #[test]
+    fn boundary_flags() {
+        assert!(parse(&["-a", "duct"]).unwrap().text);
+        assert!(parse(&["-z", "duct"]).unwrap().decompress);
+        let config = parse(&["-wx", "duct"]).unwrap();
+        assert!(config.word_regexp && config.line_regexp);
+        assert!(!parse(&["duct"]).unwrap().word_regexp);
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let config = parse(&["--", "-v", "a.txt"]).unwrap();
+        assert_eq!(vec!["-v"], config.patterns);
+        assert!(!config.invert_match);
+    }
+
+    #[test]
+    fn recursive_defaults_to_current_directory() {
+        let config = parse(&["-r", "duct"]).unwrap();
+        assert_eq!(vec!["."], config.file_paths);
+        assert_eq!(vec!["-"], parse(&["duct"]).unwrap().file_paths);
+    }
+
+    #[test]
+    fn walk_filters() {
+        let config = parse(&[
+            "--include=*.rs",
+            "--include",
+            "*.md",
+            "--exclude=target",
+            "--hidden",
+            "duct",
+        ])
+        .unwrap();
+        assert_eq!(vec!["*.rs", "*.md"], config.include);
+        assert_eq!(vec!["target"], config.exclude);
+        assert!(config.hidden);
+    }
+
+    #[test]
+    fn output_modes() {
+        assert_eq!(OutputMode::Lines, parse(&["duct", "a"]).unwrap().output);
+        assert_eq!(
+            OutputMode::FilesWithMatches,
+            parse(&["-l", "duct"]).unwrap().output
+        );
+        assert_eq!(
+            OutputMode::FilesWithoutMatch,
+            parse(&["-L", "duct"]).unwrap().output
+        );
+        // the last mode given wins
+        assert_eq!(OutputMode::Quiet, parse(&["-cq", "duct"]).unwrap().output);
+    }
+
+    #[test]
+    fn color_choice() {
+        assert_eq!(ColorChoice::Auto, parse(&["duct"]).unwrap().color);
+        assert_eq!(
+            ColorChoice::Always,
+            parse(&["--color=always", "duct"]).unwrap().color
+        );
+        assert_eq!(
+            ColorChoice::Never,
+            parse(&["--color", "never", "duct"]).unwrap().color
+        );
+        assert!(parse(&["--color=sometimes", "duct"]).is_err());
+        assert!(ColorChoice::Always.enabled(false));
+        assert!(!ColorChoice::Never.enabled(true));
+        assert!(!ColorChoice::Auto.enabled(false));
+    }
+
+    #[test]
+    fn context_values() {
+        assert_eq!(4, parse(&["-j4", "duct"]).unwrap().threads);
+        assert!(parse(&["-j", "0", "duct"]).is_err());
+        let config = parse(&["-C2", "-A", "5", "duct", "a"]).unwrap();
+        assert_eq!((2, 5), (config.before_context, config.after_context));
+        let config = parse(&["--before-context=1", "duct", "a"]).unwrap();
+        assert_eq!((1, 0), (config.before_context, config.after_context));
+        assert_eq!(
+            "invalid after-context argument 'x'",
+            parse(&["-A", "x", "duct", "a"]).unwrap_err()
+        );
+        assert!(
+            parse(&["duct", "a", "-B"])
+                .unwrap_err()
+                .contains("requires a NUM")
+        );
+    }
+
+    #[test]
+    fn errors_and_help() {
+        assert_eq!(
+            "unknown option '-y'",
+            parse(&["-y", "duct", "a"]).unwrap_err()
+        );
+        assert_eq!(
+            "option '--count' does not take a value",
+            parse(&["--count=3", "duct", "a"]).unwrap_err()
+        );
+        assert_eq!(
+            "--json cannot be combined with -c, -l or -L",
+            parse(&["--json", "-c", "duct"]).unwrap_err()
+        );
+        assert!(parse(&["--json", "duct"]).unwrap().json);
+        assert_eq!(Some(2), parse(&["--fuzzy=2", "duct"]).unwrap().fuzzy);
+        assert!(parse(&["-U", "duct"]).unwrap().multiline);
+        assert_eq!(
+            "--write cannot be combined with --json, -v, -z or -U",
+            parse(&["-U", "--replace=x", "--write", "duct", "a"]).unwrap_err()
+        );
+        assert_eq!(
+            "--fuzzy cannot be combined with -E",
+            parse(&["--fuzzy", "1", "-E", "duct"]).unwrap_err()
+        );
+        assert_eq!(
+            "--write needs --replace",
+            parse(&["--write", "duct", "a"]).unwrap_err()
+        );
+        let config = parse(&["--interactive", "-e", "duct", "src"]).unwrap();
+        assert_eq!(vec!["duct"], config.patterns);
+        assert_eq!(vec!["src"], config.file_paths);
+        assert_eq!(vec!["."], parse(&["--interactive"]).unwrap().file_paths);
+        assert_eq!(
+            "--interactive cannot be combined with --json, -c, -l, -L, -q or --replace",
+            parse(&["--interactive", "-c"]).unwrap_err()
+        );
+        assert!(parse(&["--follow", "duct", "app.log"]).unwrap().follow);
+        assert_eq!(
+            "--follow needs a FILE, not standard input",
+            parse(&["--follow", "duct"]).unwrap_err()
+        );
+        assert_eq!(
+            "--follow cannot be combined with -c, -l, -L, -q, -z, -U, --write or --interactive",
+            parse(&["--follow", "-c", "duct", "app.log"]).unwrap_err()
+        );
+        assert_eq!(
+            "--interactive cannot search standard input",
+            parse(&["--interactive", "-"]).unwrap_err()
+        );
+        assert_eq!(
+            "--write cannot rewrite standard input",
+            parse(&["--replace=x", "--write", "duct"]).unwrap_err()
+        );
+        let config = parse(&["--replace", "x", "--write", "--dry-run", "duct", "a"]).unwrap();
+        assert_eq!(Some(String::from("x")), config.replace);
+        assert!(config.write && config.dry_run);
+        assert!(parse(&["--help"]).unwrap().help);
+        assert!(usage().contains("-i, --ignore-case"));
+    }
+}
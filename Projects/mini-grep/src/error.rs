@@ -0,0 +1,124 @@
+// Errors that stop mini-grep, and the exit statuses they map to.
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::regex;
+
+// Exit statuses, following grep: a line was selected, no line was selected,
+// or something went wrong.
+pub const EXIT_MATCH: i32 = 0;
+pub const EXIT_NO_MATCH: i32 = 1;
+pub const EXIT_ERROR: i32 = 2;
+
+#[derive(Debug)]
+pub enum GrepError {
+    // the command line could not be parsed
+    Usage(String),
+    // the pattern is not a valid regular expression
+    Regex(regex::Error),
+    // a file could not be opened or read
+    Io { path: PathBuf, source: io::Error },
+}
+
+impl GrepError {
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> GrepError {
+        GrepError::Io {
+            path: path.into(),
+            source,
+        }
+    }
+
+    // Whoever read the output stopped reading, as `head` does; not worth
+    // reporting.
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(self, GrepError::Io { source, .. } if source.kind() == io::ErrorKind::BrokenPipe)
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            GrepError::Usage(_) | GrepError::Regex(_) | GrepError::Io { .. } => EXIT_ERROR,
+        }
+    }
+}
+
+impl fmt::Display for GrepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GrepError::Usage(msg) => write!(f, "{msg}"),
+            GrepError::Regex(e) => write!(f, "{e}"),
+            GrepError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+        }
+    }
+}
+
+impl Error for GrepError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GrepError::Usage(_) => None,
+            GrepError::Regex(e) => Some(e),
+            GrepError::Io { source, .. } => Some(source),
+        }
+    }
+}
+
+impl From<regex::Error> for GrepError {
+    fn from(e: regex::Error) -> GrepError {
+        GrepError::Regex(e)
+    }
+}
+
+// What a finished run found. Errors on individual files are reported as they
+// happen and do not stop the run, but they still decide the exit status.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Outcome {
+    pub matched: bool,
+    pub had_errors: bool,
+}
+
+impl Outcome {
+    pub fn exit_code(self) -> i32 {
+        if self.had_errors {
+            EXIT_ERROR
+        } else if self.matched {
+            EXIT_MATCH
+        } else {
+            EXIT_NO_MATCH
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        let outcome = |matched, had_errors| Outcome {
+            matched,
+            had_errors,
+        };
+        assert_eq!(0, outcome(true, false).exit_code());
+        assert_eq!(1, outcome(false, false).exit_code());
+        assert_eq!(2, outcome(true, true).exit_code());
+        assert_eq!(2, GrepError::Usage(String::from("bad")).exit_code());
+    }
+
+    #[test]
+    fn broken_pipes() {
+        let err = GrepError::io(
+            "(standard output)",
+            io::Error::from(io::ErrorKind::BrokenPipe),
+        );
+        assert!(err.is_broken_pipe());
+        assert!(!GrepError::io("a.txt", io::Error::from(io::ErrorKind::NotFound)).is_broken_pipe());
+    }
+
+    #[test]
+    fn io_errors_name_the_path() {
+        let err = GrepError::io("a.txt", io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!("a.txt: entity not found", err.to_string());
+        assert!(err.source().is_some());
+    }
+}
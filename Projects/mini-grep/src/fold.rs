@@ -0,0 +1,153 @@
+// Unicode case folding for case-insensitive literal search.
+//
+// Case folding maps every character to a canonical form so that strings which
+// differ only in case compare equal. Unlike `to_lowercase` on whole lines it
+// works one character at a time without allocating, and because the search
+// walks the original line, match positions always land on its own character
+// boundaries.
+//
+// Most characters fold to their lowercase form. The table below covers the
+// characters whose full folding differs from that, such as 'ß' -> "ss" and
+// final sigma -> 'σ'. Folding is locale independent, so Turkish 'İ' folds to
+// "i\u{307}" (i with combining dot above) as in Unicode's default folding.
+use std::char::ToLowercase;
+use std::ops::Range;
+use std::str::Chars;
+
+const SPECIAL: &[(char, &str)] = &[
+    ('ß', "ss"),
+    ('ẞ', "ss"),
+    ('ς', "σ"),
+    ('ſ', "s"),
+    ('ẛ', "ṡ"),
+    ('\u{1FBE}', "\u{3B9}"),
+    ('ϐ', "β"),
+    ('ϑ', "θ"),
+    ('ϕ', "φ"),
+    ('ϖ', "π"),
+    ('ϰ', "κ"),
+    ('ϱ', "ρ"),
+    ('ϵ', "ε"),
+    ('ŉ', "ʼn"),
+    ('ﬀ', "ff"),
+    ('ﬁ', "fi"),
+    ('ﬂ', "fl"),
+    ('ﬃ', "ffi"),
+    ('ﬄ', "ffl"),
+    ('ﬅ', "st"),
+    ('ﬆ', "st"),
+];
+
+// The folded form of one character: one to three characters.
+pub enum Fold {
+    Special(Chars<'static>),
+    Lower(ToLowercase),
+}
+
+impl Iterator for Fold {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        match self {
+            Fold::Special(chars) => chars.next(),
+            Fold::Lower(chars) => chars.next(),
+        }
+    }
+}
+
+pub fn fold(c: char) -> Fold {
+    // ASCII never needs the table; skip the lookup on the hot path
+    if !c.is_ascii()
+        && let Some((_, folded)) = SPECIAL.iter().find(|(from, _)| *from == c)
+    {
+        return Fold::Special(folded.chars());
+    }
+    Fold::Lower(c.to_lowercase())
+}
+
+// A literal that matches any text with the same case folding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoldedLiteral {
+    needle: Vec<char>,
+}
+
+impl FoldedLiteral {
+    pub fn new(query: &str) -> FoldedLiteral {
+        FoldedLiteral {
+            needle: query.chars().flat_map(fold).collect(),
+        }
+    }
+
+    // Byte range of the first match in `line`. A match must cover whole
+    // characters of the line, so "s" does not match half of "ß".
+    pub fn find(&self, line: &str) -> Option<Range<usize>> {
+        let Some(&first) = self.needle.first() else {
+            return Some(0..0);
+        };
+        for (start, c) in line.char_indices() {
+            if fold(c).next() != Some(first) {
+                continue;
+            }
+            if let Some(len) = self.match_len(&line[start..]) {
+                return Some(start..start + len);
+            }
+        }
+        None
+    }
+
+    // Length in bytes of the prefix of `text` that folds to the needle.
+    fn match_len(&self, text: &str) -> Option<usize> {
+        let mut matched = 0;
+        for (i, c) in text.char_indices() {
+            for f in fold(c) {
+                if self.needle.get(matched) != Some(&f) {
+                    return None;
+                }
+                matched += 1;
+            }
+            if matched == self.needle.len() {
+                return Some(i + c.len_utf8());
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find<'a>(query: &str, line: &'a str) -> Option<&'a str> {
+        FoldedLiteral::new(query).find(line).map(|span| &line[span])
+    }
+
+    #[test]
+    fn ascii_and_simple_folding() {
+        assert_eq!(Some("RuSt"), find("rUsT", "I said RuSt"));
+        assert_eq!(Some("ΣΊΣΥΦΟΣ"), find("σίσυφος", "ΣΊΣΥΦΟΣ"));
+        assert_eq!(None, find("duct", "Duck tape"));
+    }
+
+    #[test]
+    fn expanding_characters() {
+        assert_eq!(Some("STRASSE"), find("straße", "die STRASSE"));
+        assert_eq!(Some("Straße"), find("STRASSE", "die Straße"));
+        assert_eq!(Some("ẞ"), find("ss", "GROẞ"));
+        // partial characters never match
+        assert_eq!(None, find("s", "ß"));
+        assert_eq!(Some("ﬁle"), find("FILE", "a ﬁle"));
+    }
+
+    #[test]
+    fn dotted_capital_i() {
+        assert_eq!(Some("İ"), find("i\u{307}", "İstanbul"));
+        assert_eq!(Some("İstanbul"), find("İSTANBUL", "İstanbul"));
+        // offsets after the two-byte 'İ' stay on the original line
+        assert_eq!(Some(8..12), FoldedLiteral::new("rust").find("İ love RUST"));
+    }
+
+    #[test]
+    fn empty_query_matches_everywhere() {
+        assert_eq!(Some(0..0), FoldedLiteral::new("").find("anything"));
+    }
+}
@@ -0,0 +1,412 @@
+// --follow: keeps searching files as they grow, like `tail -f` piped into
+// mini-grep.
+//
+// Each file is searched from its start once and then polled for appended
+// data. Only complete lines are searched, so a line that is still being
+// written waits for its newline, unless it grows past a whole chunk. A file
+// that got shorter, or whose start changed, was truncated and is searched
+// again from its start. When the path names another file than the one
+// open, the file was rotated: the rest of the old file is read first, and
+// then the new one is followed from its start. SIGINT and SIGTERM end the
+// run like the end of an ordinary search, so --json gets its end records.
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::{
+    Config, GrepError, Match, Matcher, Outcome, Pattern, Printer, Searcher, Sink, Stats, memchr,
+};
+
+// How long to wait when no file had anything new.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// The most read from a file at once, so a large file is never held in
+// memory whole.
+const CHUNK: u64 = 1 << 20;
+
+// How much of the start of a file is kept, to notice the file being
+// truncated and written again past where it was read to between polls.
+const PREFIX: usize = 4096;
+
+// Set by SIGINT and SIGTERM.
+static STOPPED: AtomicBool = AtomicBool::new(false);
+
+// Follows every file in the config until the process is interrupted or
+// terminated; only an error ends it early.
+pub(crate) fn run(config: &Config, pattern: &Pattern, color: bool) -> Result<Outcome, GrepError> {
+    let mut files = Vec::new();
+    for file_path in &config.file_paths {
+        if Path::new(file_path).is_dir() {
+            return Err(GrepError::Usage(format!(
+                "--follow cannot follow the directory {file_path}"
+            )));
+        }
+        let printer = Printer::new(config, io::stdout()).color(color);
+        let followed =
+            Followed::open(file_path.as_ref(), printer).map_err(|e| GrepError::io(file_path, e))?;
+        files.push(followed);
+    }
+    let searcher = Searcher::from(config);
+    stop_on_signals();
+    while !STOPPED.load(Ordering::Relaxed) {
+        let mut read_any = false;
+        for followed in &mut files {
+            read_any |= followed
+                .poll(&searcher, pattern)
+                .map_err(|e| GrepError::io(&followed.path, e))?;
+        }
+        if !read_any {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+    let mut outcome = Outcome::default();
+    let mut stats = Stats::default();
+    for followed in &mut files {
+        followed
+            .finish(&searcher, pattern)
+            .map_err(|e| GrepError::io(&followed.path, e))?;
+        outcome.matched |= followed.selected > 0;
+        stats += followed.printer.stats();
+    }
+    Printer::new(config, io::stdout())
+        .summary(stats)
+        .map_err(|e| GrepError::io("(standard output)", e))?;
+    Ok(outcome)
+}
+
+#[cfg(unix)]
+fn stop_on_signals() {
+    use std::ffi::c_int;
+
+    const SIGINT: c_int = 2;
+    const SIGTERM: c_int = 15;
+
+    unsafe extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+
+    extern "C" fn stop(_signum: c_int) {
+        STOPPED.store(true, Ordering::Relaxed);
+    }
+
+    // SAFETY: the handler only stores to an atomic, which is safe to do
+    // from a signal handler
+    unsafe {
+        signal(SIGINT, stop);
+        signal(SIGTERM, stop);
+    }
+}
+
+#[cfg(not(unix))]
+fn stop_on_signals() {}
+
+struct Followed<W: Write> {
+    path: PathBuf,
+    file: File,
+    // how much of the file has been read
+    pos: u64,
+    // what was read after the last complete line
+    partial: Vec<u8>,
+    // the first `PREFIX` bytes of the file, or as many as were read
+    prefix: Vec<u8>,
+    // the lines and bytes of the file before `partial`
+    lines: usize,
+    offset: usize,
+    // the lines selected so far, for the end of the file in --json
+    selected: usize,
+    // "Binary file ... matches" was printed, and the file is not searched
+    // any further
+    binary: bool,
+    // each file has its own, which keeps its context apart from the others'
+    printer: Printer<W>,
+}
+
+impl<W: Write> Followed<W> {
+    fn open(path: &Path, mut printer: Printer<W>) -> io::Result<Followed<W>> {
+        let file = File::open(path)?;
+        printer.begin(path);
+        Ok(Followed {
+            path: path.to_path_buf(),
+            file,
+            pos: 0,
+            partial: Vec::new(),
+            prefix: Vec::new(),
+            lines: 0,
+            offset: 0,
+            selected: 0,
+            binary: false,
+            printer,
+        })
+    }
+
+    // Searches what was appended since the last poll, and notices when the
+    // file was truncated or rotated. Returns whether there was anything new.
+    fn poll<M: Matcher + ?Sized>(&mut self, searcher: &Searcher, matcher: &M) -> io::Result<bool> {
+        if self.truncated()? {
+            eprintln!("mini-grep: {}: file truncated", self.path.display());
+            self.restart()?;
+        }
+        if self.read(searcher, matcher)? {
+            return Ok(true);
+        }
+        // the old file is read to its end by now
+        if self.rotated()
+            && let Ok(file) = File::open(&self.path)
+        {
+            eprintln!(
+                "mini-grep: {}: file replaced; following the new file",
+                self.path.display()
+            );
+            // the old file will not get the rest of its last line
+            self.search(searcher, matcher, self.partial.len())?;
+            self.file = file;
+            self.restart()?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    // Reads the next chunk and searches the lines it completes.
+    fn read<M: Matcher + ?Sized>(&mut self, searcher: &Searcher, matcher: &M) -> io::Result<bool> {
+        self.file.seek(SeekFrom::Start(self.pos))?;
+        let start = self.partial.len();
+        let read = (&mut self.file)
+            .take(CHUNK)
+            .read_to_end(&mut self.partial)?;
+        if read == 0 {
+            return Ok(false);
+        }
+        if self.prefix.len() < PREFIX {
+            let more = (PREFIX - self.prefix.len()).min(read);
+            self.prefix
+                .extend_from_slice(&self.partial[start..start + more]);
+        }
+        self.pos += read as u64;
+        if let Some(newline) = memchr::memrchr(b'\n', &self.partial[start..]) {
+            self.search(searcher, matcher, start + newline + 1)?;
+        }
+        // a line longer than a chunk is searched in pieces instead of being
+        // held whole
+        if self.partial.len() as u64 >= CHUNK {
+            self.search(searcher, matcher, self.partial.len())?;
+        }
+        Ok(true)
+    }
+
+    // Whether the file is shorter than what was read of it, or starts
+    // differently than it did.
+    fn truncated(&mut self) -> io::Result<bool> {
+        if self.file.metadata()?.len() < self.pos {
+            return Ok(true);
+        }
+        let mut start = vec![0; self.prefix.len()];
+        self.file.seek(SeekFrom::Start(0))?;
+        match self.file.read_exact(&mut start) {
+            Ok(()) => Ok(start != self.prefix),
+            // shortened since the length was looked at
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Searches the first `end` bytes of `partial` and drops them.
+    fn search<M: Matcher + ?Sized>(
+        &mut self,
+        searcher: &Searcher,
+        matcher: &M,
+        end: usize,
+    ) -> io::Result<()> {
+        if end == 0 {
+            return Ok(());
+        }
+        let lines = &self.partial[..end];
+        if !self.binary {
+            let mut sink = Shifted {
+                sink: &mut self.printer,
+                lines: self.lines,
+                offset: self.offset,
+                binary: &mut self.binary,
+            };
+            self.selected += searcher.search_slice(matcher, lines, &mut sink)?;
+        }
+        self.lines += memchr::count(b'\n', lines);
+        self.offset += end;
+        self.partial.drain(..end);
+        Ok(())
+    }
+
+    // Ends the file with what is left of its last line, which is not
+    // getting its newline any more.
+    fn finish<M: Matcher + ?Sized>(&mut self, searcher: &Searcher, matcher: &M) -> io::Result<()> {
+        self.search(searcher, matcher, self.partial.len())?;
+        self.printer.finish(self.selected)
+    }
+
+    // Ends the file that was followed and starts on what the path holds now.
+    fn restart(&mut self) -> io::Result<()> {
+        self.printer.finish(self.selected)?;
+        self.printer.begin(&self.path);
+        self.pos = 0;
+        self.partial.clear();
+        self.prefix.clear();
+        self.lines = 0;
+        self.offset = 0;
+        self.selected = 0;
+        self.binary = false;
+        Ok(())
+    }
+
+    // Whether the path now names a different file than the open one.
+    #[cfg(unix)]
+    fn rotated(&self) -> bool {
+        use std::os::unix::fs::MetadataExt;
+        let (Ok(open), Ok(named)) = (self.file.metadata(), std::fs::metadata(&self.path)) else {
+            return false;
+        };
+        (open.dev(), open.ino()) != (named.dev(), named.ino())
+    }
+
+    #[cfg(not(unix))]
+    fn rotated(&self) -> bool {
+        false
+    }
+}
+
+// Passes on what a search of some later part of a file found, with line
+// numbers and offsets counted from the start of the file. The end of that
+// part is not the end of the file, so `finish` is not passed on.
+struct Shifted<'a, S: Sink + ?Sized> {
+    sink: &'a mut S,
+    lines: usize,
+    offset: usize,
+    // set once the sink has been told the file is binary
+    binary: &'a mut bool,
+}
+
+impl<S: Sink + ?Sized> Shifted<'_, S> {
+    fn shift<'m>(&self, m: &Match<'m>) -> Match<'m> {
+        Match {
+            line_number: self.lines + m.line_number,
+            byte_offset: self.offset + m.byte_offset,
+            ..m.clone()
+        }
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for Shifted<'_, S> {
+    fn matched(&mut self, m: &Match, spans: &[Range<usize>]) -> io::Result<bool> {
+        let m = self.shift(m);
+        self.sink.matched(&m, spans)
+    }
+
+    fn context(&mut self, line_number: usize, line: &str) -> io::Result<bool> {
+        self.sink.context(self.lines + line_number, line)
+    }
+
+    fn binary(&mut self, m: &Match, spans: &[Range<usize>]) -> io::Result<bool> {
+        let m = self.shift(m);
+        *self.binary = true;
+        self.sink.binary(&m, spans)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempdir::TempDir;
+    use std::fs;
+
+    #[test]
+    fn appends_truncation_and_rotation() {
+        let dir = TempDir::new("follow");
+        let path = dir.join("app.log");
+        let append = |path: &Path, text: &str| {
+            let mut file = fs::OpenOptions::new().append(true).open(path).unwrap();
+            file.write_all(text.as_bytes()).unwrap();
+        };
+        let config = Config {
+            patterns: vec![String::from("match")],
+            line_number: true,
+            file_paths: vec![path.display().to_string()],
+            ..Config::default()
+        };
+        let pattern = Pattern::new(&config).unwrap();
+        let searcher = Searcher::from(&config);
+
+        fs::write(&path, "a match\nno\npart").unwrap();
+        let printer = Printer::new(&config, Vec::new());
+        let mut followed = Followed::open(&path, printer).unwrap();
+        assert!(followed.poll(&searcher, &pattern).unwrap());
+        assert!(!followed.poll(&searcher, &pattern).unwrap());
+        // the rest of a line that was being written
+        append(&path, "ial match\n");
+        followed.poll(&searcher, &pattern).unwrap();
+        // truncated and written again
+        fs::write(&path, "match again\n").unwrap();
+        followed.poll(&searcher, &pattern).unwrap();
+        // and again, past where it had been read to
+        fs::write(&path, "a longer line\nwith a match\n").unwrap();
+        followed.poll(&searcher, &pattern).unwrap();
+        // rotated, with a last line written to the old file
+        fs::rename(&path, dir.join("app.log.1")).unwrap();
+        fs::write(&path, "new match\n").unwrap();
+        append(&dir.join("app.log.1"), "old match\n");
+        while followed.poll(&searcher, &pattern).unwrap() {}
+
+        let output = String::from_utf8(followed.printer.into_inner()).unwrap();
+        assert_eq!(
+            "1:a match\n3:partial match\n1:match again\n2:with a match\n3:old match\n1:new match\n",
+            output
+        );
+    }
+
+    #[test]
+    fn binary_files_long_lines_and_json_ends() {
+        let dir = TempDir::new("follow-end");
+        let path = dir.join("app.log");
+        let mut config = Config {
+            patterns: vec![String::from("match")],
+            file_paths: vec![path.display().to_string()],
+            ..Config::default()
+        };
+        let pattern = Pattern::new(&config).unwrap();
+        let searcher = Searcher::from(&config);
+        let follow = |contents: &[&[u8]], config: &Config| {
+            fs::write(&path, "").unwrap();
+            let printer = Printer::new(config, Vec::new());
+            let mut followed = Followed::open(&path, printer).unwrap();
+            for more in contents {
+                let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+                file.write_all(more).unwrap();
+                while followed.poll(&searcher, &pattern).unwrap() {}
+            }
+            followed
+        };
+
+        // the notice is printed once, not for every part of the file read
+        let followed = follow(&[b"a match\0\n", b"another match\n"], &config);
+        let output = String::from_utf8(followed.printer.into_inner()).unwrap();
+        assert_eq!(format!("Binary file {} matches\n", path.display()), output);
+
+        // a line without a newline is not held past a chunk
+        let long = format!("match {}", "x".repeat(CHUNK as usize));
+        let followed = follow(&[long.as_bytes()], &config);
+        assert!(followed.partial.len() < CHUNK as usize);
+        assert_eq!(1, followed.selected);
+
+        config.json = true;
+        let mut followed = follow(&[b"a match\n", b"the last match"], &config);
+        followed.finish(&searcher, &pattern).unwrap();
+        let output = String::from_utf8(followed.printer.into_inner()).unwrap();
+        let end = output.lines().last().unwrap();
+        assert!(end.starts_with(r#"{"type":"end","#), "{output}");
+        assert!(
+            end.ends_with(r#""matched_lines":2,"matches":2}"#),
+            "{output}"
+        );
+    }
+}
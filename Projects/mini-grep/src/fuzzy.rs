@@ -0,0 +1,291 @@
+// Approximate matching for `--fuzzy=N`: finds a pattern in a line with at
+// most N edits (inserted, deleted or substituted characters).
+//
+// This is the bit-parallel "bitap" algorithm with errors from Wu and Manber.
+// For every error count d up to N, one 64-bit word holds which prefixes of
+// the pattern end at the current text position with at most d edits, and
+// each text character updates all of them with a handful of shifts and ANDs.
+// A match ends wherever the bit of the whole pattern is set; the smallest d
+// for which it is set is the distance. Where the match starts is then found
+// with a small dynamic program over the few characters before its end.
+use std::ops::Range;
+
+use crate::fold::fold;
+
+// Patterns are limited to the width of one machine word.
+pub const MAX_LEN: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct Fuzzy {
+    pattern: Vec<char>,
+    max_distance: usize,
+    case_insensitive: bool,
+    // bit i of a character's mask is set where the pattern has it
+    masks: Masks,
+}
+
+// Buffers kept from one call of `Fuzzy::starts` to the next, so that -w
+// and -x, which call it at many ends, do not allocate each time.
+#[derive(Debug, Default)]
+pub struct Scratch {
+    // the characters before the end, last first, each with its offset
+    before: Vec<(usize, char)>,
+    reversed: Vec<char>,
+    row: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct Masks {
+    ascii: [u64; 128],
+    other: Vec<(char, u64)>,
+}
+
+impl Masks {
+    fn new(pattern: impl Iterator<Item = char>) -> Masks {
+        let mut masks = Masks {
+            ascii: [0; 128],
+            other: Vec::new(),
+        };
+        for (i, c) in pattern.enumerate() {
+            let bit = 1 << i;
+            if c.is_ascii() {
+                masks.ascii[c as usize] |= bit;
+            } else if let Some(entry) = masks.other.iter_mut().find(|(k, _)| *k == c) {
+                entry.1 |= bit;
+            } else {
+                masks.other.push((c, bit));
+            }
+        }
+        masks
+    }
+
+    fn get(&self, c: char) -> u64 {
+        if c.is_ascii() {
+            return self.ascii[c as usize];
+        }
+        self.other
+            .iter()
+            .find(|(k, _)| *k == c)
+            .map_or(0, |&(_, mask)| mask)
+    }
+}
+
+impl Fuzzy {
+    // `None` if the pattern is longer than `MAX_LEN` characters.
+    pub fn new(pattern: &str, max_distance: usize, case_insensitive: bool) -> Option<Fuzzy> {
+        let fold_char = |c| fold_one(c, case_insensitive);
+        let pattern: Vec<char> = pattern.chars().map(fold_char).collect();
+        if pattern.len() > MAX_LEN {
+            return None;
+        }
+        Some(Fuzzy {
+            masks: Masks::new(pattern.iter().copied()),
+            pattern,
+            max_distance,
+            case_insensitive,
+        })
+    }
+
+    // The best match in `text` and its distance: the one with the fewest
+    // edits, the leftmost of those on a tie.
+    pub fn find(&self, text: &str) -> Option<(Range<usize>, usize)> {
+        if self.pattern.is_empty() {
+            return Some((0..0, 0));
+        }
+        let mut best: Option<(usize, usize)> = None;
+        for (end, distance) in self.ends(text) {
+            if best.is_none_or(|(_, d)| distance < d) {
+                best = Some((end, distance));
+            }
+            // nothing can beat an exact match
+            if distance == 0 {
+                break;
+            }
+        }
+        let (end, distance) = best?;
+        // take the shortest match that has the same distance
+        let (start, _) = self
+            .starts(&text[..end], &mut Scratch::default())
+            .find(|&(_, d)| d == distance)?;
+        Some((start..end, distance))
+    }
+
+    // Every byte offset in `text` where a match ends, with the fewest edits
+    // of the matches that end there.
+    pub fn ends<'a>(&'a self, text: &'a str) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let len = self.pattern.len();
+        let k = self.max_distance.min(len);
+        let done = 1u64 << len.saturating_sub(1);
+        // with d edits, the first d pattern characters may be deleted
+        let mut states: Vec<u64> = (0..=k).map(low_bits).collect();
+        // before the first character, only by deleting all of the pattern
+        let empty = (len <= k).then_some((0, len));
+        let rest = text.char_indices().filter_map(move |(i, c)| {
+            let mask = self.masks.get(fold_one(c, self.case_insensitive));
+            let mut prev_old = states[0];
+            states[0] = ((states[0] << 1) | 1) & mask;
+            for d in 1..=k {
+                let old = states[d];
+                states[d] = (((old << 1) | 1) & mask)
+                    // substitution
+                    | (prev_old << 1) | 1
+                    // an extra character in the text
+                    | prev_old
+                    // a pattern character missing from the text
+                    | (states[d - 1] << 1);
+                prev_old = old;
+            }
+            let distance = if len == 0 {
+                Some(0)
+            } else {
+                states.iter().position(|s| s & done != 0)
+            };
+            distance.map(|d| (i + c.len_utf8(), d))
+        });
+        empty.into_iter().chain(rest)
+    }
+
+    // The matches that end where `text` ends, as the byte offset where each
+    // starts and its distance, shortest first. A match with d edits is at
+    // most d characters longer than the pattern, so only that many
+    // characters are looked at.
+    pub fn starts<'a>(
+        &self,
+        text: &str,
+        scratch: &'a mut Scratch,
+    ) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let Scratch {
+            before,
+            reversed,
+            row,
+        } = scratch;
+        before.clear();
+        before.push((text.len(), '\0'));
+        before.extend(
+            text.char_indices()
+                .rev()
+                .take(self.pattern.len() + self.max_distance)
+                .map(|(i, c)| (i, fold_one(c, self.case_insensitive))),
+        );
+        reversed.clear();
+        reversed.extend(before[1..].iter().map(|&(_, c)| c));
+        edit_distances(self.pattern.iter().rev(), reversed, row);
+        let max_distance = self.max_distance;
+        before
+            .iter()
+            .zip(row.iter())
+            .map(|(&(start, _), &d)| (start, d))
+            .filter(move |&(_, d)| d <= max_distance)
+    }
+
+    // The edit distance between the pattern and all of `text`.
+    pub fn distance(&self, text: &str) -> usize {
+        let text: Vec<char> = text
+            .chars()
+            .map(|c| fold_one(c, self.case_insensitive))
+            .collect();
+        let mut row = Vec::new();
+        edit_distances(self.pattern.iter(), &text, &mut row);
+        row[text.len()]
+    }
+}
+
+// The edit distance between all of `pattern` and each prefix of `text`,
+// by prefix length: the last row of the Levenshtein table, left in `row`.
+fn edit_distances<'a>(
+    pattern: impl Iterator<Item = &'a char>,
+    text: &[char],
+    row: &mut Vec<usize>,
+) {
+    row.clear();
+    row.extend(0..=text.len());
+    for (i, &p) in pattern.enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &t) in text.iter().enumerate() {
+            let cost = diagonal + usize::from(p != t);
+            diagonal = row[j + 1];
+            row[j + 1] = cost.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+}
+
+fn low_bits(n: usize) -> u64 {
+    if n >= 64 { u64::MAX } else { (1 << n) - 1 }
+}
+
+// Case folding one character to one, so text and pattern positions line up;
+// characters such as 'ß' that fold to several are compared unfolded.
+fn fold_one(c: char, case_insensitive: bool) -> char {
+    if !case_insensitive {
+        return c;
+    }
+    let mut folded = fold(c);
+    match (folded.next(), folded.next()) {
+        (Some(f), None) => f,
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find<'a>(pattern: &str, k: usize, text: &'a str) -> Option<(&'a str, usize)> {
+        let fuzzy = Fuzzy::new(pattern, k, false).unwrap();
+        fuzzy.find(text).map(|(span, d)| (&text[span], d))
+    }
+
+    #[test]
+    fn exact_matches_have_distance_zero() {
+        assert_eq!(Some(("timeout", 0)), find("timeout", 2, "a timeout here"));
+    }
+
+    #[test]
+    fn each_kind_of_edit() {
+        // substitution, insertion and deletion
+        assert_eq!(Some(("tineout", 1)), find("timeout", 1, "a tineout here"));
+        assert_eq!(Some(("timeeout", 1)), find("timeout", 1, "timeeout"));
+        assert_eq!(Some(("timout", 1)), find("timeout", 1, "got timout!"));
+        assert_eq!(None, find("timeout", 1, "tmout"));
+        assert_eq!(Some(("tmout", 2)), find("timeout", 2, "tmout"));
+    }
+
+    #[test]
+    fn best_match_wins() {
+        // the exact match later in the line beats the earlier typo
+        assert_eq!(Some(("connect", 0)), find("connect", 2, "conect, connect"));
+        assert_eq!(Some(("conect", 1)), find("connect", 2, "conect, conect"));
+    }
+
+    #[test]
+    fn case_insensitive_and_unicode() {
+        let fuzzy = Fuzzy::new("Größe", 1, true).unwrap();
+        let text = "die GRÖSE";
+        let (span, d) = fuzzy.find(text).unwrap();
+        assert_eq!(("GRÖSE", 1), (&text[span], d));
+        assert_eq!(1, fuzzy.distance("GRÖSE"));
+    }
+
+    #[test]
+    fn distance_is_levenshtein() {
+        let fuzzy = Fuzzy::new("kitten", 3, false).unwrap();
+        assert_eq!(3, fuzzy.distance("sitting"));
+        assert_eq!(0, fuzzy.distance("kitten"));
+    }
+
+    #[test]
+    fn ends_and_starts() {
+        let fuzzy = Fuzzy::new("kitten", 3, false).unwrap();
+        // "sitt", "sitti", "sittin" and "sitting", by where they end
+        let ends: Vec<_> = fuzzy.ends("sitting down").collect();
+        assert_eq!(vec![(4, 3), (5, 3), (6, 2), (7, 3)], ends);
+        // "ttin", "ittin" and "sittin" end there, by where they start
+        let mut scratch = Scratch::default();
+        let starts: Vec<_> = fuzzy.starts("sittin", &mut scratch).collect();
+        assert_eq!(vec![(2, 3), (1, 2), (0, 2)], starts);
+        let empty = Fuzzy::new("", 1, false).unwrap();
+        assert_eq!(vec![(0, 0), (1, 0)], empty.ends("a").collect::<Vec<_>>());
+        assert!(Fuzzy::new(&"x".repeat(65), 1, false).is_none());
+    }
+}
@@ -0,0 +1,193 @@
+// Shell-style glob patterns, as used by `.gitignore` and `--include`.
+//
+// `*` matches any run of characters within one path component, `?` a single
+// character and `[a-z]` (or `[!a-z]`) one character from a set; none of them
+// match '/'. A `**` component matches any number of directories: `**/name`,
+// `dir/**` and `a/**/b` behave as in git. A backslash makes the next
+// character literal. Paths are matched with '/' as the separator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    // ?
+    Any,
+    // *
+    Star,
+    // `**` at the end of a pattern: everything, including '/'
+    AnyPath,
+    // `**/`: nothing, or anything that ends with '/'
+    Dirs,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glob {
+    pattern: String,
+    tokens: Vec<Token>,
+}
+
+impl Glob {
+    // Every string is a valid glob: a '[' without its ']' is taken literally.
+    pub fn new(pattern: &str) -> Glob {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    // `**` is only special as a whole path component;
+                    // elsewhere it is an ordinary `*`
+                    let starts_component = i == 0 || chars[i - 1] == '/';
+                    match chars.get(i + 2) {
+                        None if starts_component => tokens.push(Token::AnyPath),
+                        Some('/') if starts_component => {
+                            tokens.push(Token::Dirs);
+                            i += 1;
+                        }
+                        _ => tokens.push(Token::Star),
+                    }
+                    i += 1;
+                }
+                '*' => tokens.push(Token::Star),
+                '?' => tokens.push(Token::Any),
+                '[' => match parse_class(&chars[i + 1..]) {
+                    Some((class, len)) => {
+                        tokens.push(class);
+                        i += len;
+                    }
+                    None => tokens.push(Token::Char('[')),
+                },
+                '\\' if i + 1 < chars.len() => {
+                    i += 1;
+                    tokens.push(Token::Char(chars[i]));
+                }
+                c => tokens.push(Token::Char(c)),
+            }
+            i += 1;
+        }
+        Glob {
+            pattern: pattern.to_string(),
+            tokens,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    // Whether the glob matches all of `path`.
+    pub fn is_match(&self, path: &str) -> bool {
+        let text: Vec<char> = path.chars().collect();
+        let (n, m) = (self.tokens.len(), text.len());
+        // matched[i][j]: tokens[i..] match text[j..]; filled in from the end,
+        // which keeps `*` and `**` linear instead of backtracking
+        let mut matched = vec![vec![false; m + 1]; n + 1];
+        matched[n][m] = true;
+        for i in (0..n).rev() {
+            // for `**/`: whether some '/' at or after j ends what it covers
+            let mut dirs = false;
+            for j in (0..=m).rev() {
+                let c = text.get(j).copied();
+                let single = |ok: bool| ok && matched[i + 1][j + 1];
+                matched[i][j] = match &self.tokens[i] {
+                    Token::Char(want) => single(c == Some(*want)),
+                    Token::Any => single(c.is_some_and(|c| c != '/')),
+                    Token::Class { negated, ranges } => single(c.is_some_and(|c| {
+                        c != '/' && ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated
+                    })),
+                    Token::Star => {
+                        matched[i + 1][j] || (c.is_some_and(|c| c != '/') && matched[i][j + 1])
+                    }
+                    Token::AnyPath => matched[i + 1][j] || (c.is_some() && matched[i][j + 1]),
+                    Token::Dirs => {
+                        dirs |= c == Some('/') && matched[i + 1][j + 1];
+                        matched[i + 1][j] || dirs
+                    }
+                };
+            }
+        }
+        matched[0][0]
+    }
+}
+
+// Parses the set after a '[', returning it and how many characters it used.
+fn parse_class(chars: &[char]) -> Option<(Token, usize)> {
+    let mut i = 0;
+    let negated = matches!(chars.first(), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    // a ']' right at the start is part of the set
+    let start = i;
+    loop {
+        let mut c = *chars.get(i)?;
+        if c == ']' && i > start {
+            return Some((Token::Class { negated, ranges }, i + 1));
+        }
+        if c == '\\' {
+            i += 1;
+            c = *chars.get(i)?;
+        }
+        match (chars.get(i + 1), chars.get(i + 2)) {
+            (Some('-'), Some(&hi)) if hi != ']' => {
+                ranges.push((c, hi));
+                i += 3;
+            }
+            _ => {
+                ranges.push((c, c));
+                i += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, path: &str) -> bool {
+        Glob::new(pattern).is_match(path)
+    }
+
+    #[test]
+    fn wildcards_stay_within_a_component() {
+        assert!(is_match("*.rs", "main.rs"));
+        assert!(!is_match("*.rs", "src/main.rs"));
+        assert!(is_match("src/*.rs", "src/main.rs"));
+        assert!(is_match("ma?n.rs", "main.rs"));
+        assert!(!is_match("a?b", "a/b"));
+    }
+
+    #[test]
+    fn double_star_crosses_directories() {
+        assert!(is_match("**/target", "target"));
+        assert!(is_match("**/target", "a/b/target"));
+        assert!(is_match("target/**", "target/debug/build"));
+        assert!(!is_match("target/**", "target"));
+        assert!(is_match("a/**/b", "a/b"));
+        assert!(is_match("a/**/b", "a/x/y/b"));
+        assert!(!is_match("a/**/b", "a/xb"));
+        // not a whole component, so just a `*`
+        assert!(is_match("a**b", "axxb"));
+        assert!(!is_match("a**b", "a/b"));
+    }
+
+    #[test]
+    fn character_classes() {
+        assert!(is_match("[abc].txt", "b.txt"));
+        assert!(is_match("file[0-9]", "file7"));
+        assert!(!is_match("file[!0-9]", "file7"));
+        assert!(is_match("[]x]", "]"));
+        // unclosed, so a literal '['
+        assert!(is_match("a[b", "a[b"));
+    }
+
+    #[test]
+    fn escapes_are_literal() {
+        assert!(is_match(r"\*.txt", "*.txt"));
+        assert!(!is_match(r"\*.txt", "a.txt"));
+    }
+}
@@ -0,0 +1,592 @@
+// Streaming gzip decompression for `-z`, with a self-contained inflate.
+//
+// A gzip file (RFC 1952) is one or more members, each a small header, a
+// DEFLATE stream (RFC 1951) and a trailer holding the CRC-32 and length of
+// the data. `GzDecoder` decodes a block at a time into a small buffer as the
+// caller reads, keeping only the last 32 KiB of output for back-references,
+// so memory use does not grow with the file.
+//
+// Huffman codes are decoded one bit at a time by walking the canonical code
+// lengths, as zlib's `puff` does: slower than table lookups, but short and
+// hard to get wrong.
+use std::io::{self, BufRead, Read};
+
+// The first two bytes of every gzip member.
+pub const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+const WINDOW: usize = 32 * 1024;
+const MAX_BITS: usize = 15;
+// stop filling the output buffer around here and let the caller drain it
+const CHUNK: usize = 32 * 1024;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// the order in which code length code lengths are stored
+const CLEN_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    let mut c = !crc;
+    for &b in bytes {
+        c = CRC_TABLE[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid gzip data: {msg}"),
+    )
+}
+
+// Reads a stream least significant bit first, as DEFLATE packs it.
+struct BitReader<R> {
+    inner: R,
+    bits: u64,
+    count: u32,
+}
+
+impl<R: BufRead> BitReader<R> {
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        while self.count < n {
+            let byte = self
+                .next_byte()?
+                .ok_or_else(|| invalid("unexpected end of file"))?;
+            self.bits |= (byte as u64) << self.count;
+            self.count += 8;
+        }
+        let value = (self.bits & ((1 << n) - 1)) as u32;
+        self.bits >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    // Drops the bits left in the current byte.
+    fn align(&mut self) {
+        let extra = self.count % 8;
+        self.bits >>= extra;
+        self.count -= extra;
+    }
+
+    // The next whole byte, or `None` at the end of the input; only called
+    // on a byte boundary.
+    fn byte_or_eof(&mut self) -> io::Result<Option<u8>> {
+        if self.count >= 8 {
+            return self.bits(8).map(|b| Some(b as u8));
+        }
+        self.next_byte()
+    }
+
+    // The next byte of the underlying input, past any buffered bits.
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        let buf = self.inner.fill_buf()?;
+        let Some(&byte) = buf.first() else {
+            return Ok(None);
+        };
+        self.inner.consume(1);
+        Ok(Some(byte))
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        self.bits(8).map(|b| b as u8)
+    }
+
+    fn u16_le(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes([self.byte()?, self.byte()?]))
+    }
+
+    fn u32_le(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes([
+            self.byte()?,
+            self.byte()?,
+            self.byte()?,
+            self.byte()?,
+        ]))
+    }
+}
+
+// A canonical Huffman code: how many codes there are of each length, and
+// the symbols ordered by code.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Huffman> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        // no length may have more codes than the shorter ones leave room for
+        let mut left: i32 = 1;
+        for &count in &counts[1..] {
+            left = left * 2 - count as i32;
+            if left < 0 {
+                return Err(invalid("over-subscribed Huffman code"));
+            }
+        }
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode<R: BufRead>(&self, input: &mut BitReader<R>) -> io::Result<u16> {
+        // `code` is the bits read so far; `first` is the first code of the
+        // current length and `index` the position of its symbol
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..=MAX_BITS {
+            code |= input.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("bad Huffman code"))
+    }
+}
+
+enum State {
+    // at the start of a member, or at the end of the input after one
+    Header,
+    // between blocks; the flag says the last block is done
+    Block {
+        last: bool,
+    },
+    Stored {
+        left: usize,
+        last: bool,
+    },
+    Codes {
+        lit: Huffman,
+        dist: Huffman,
+        last: bool,
+    },
+    Done,
+}
+
+pub struct GzDecoder<R> {
+    input: BitReader<R>,
+    state: State,
+    members: usize,
+    // the last WINDOW bytes of output, as a ring
+    window: Vec<u8>,
+    // bytes written to the current member so far
+    written: usize,
+    crc: u32,
+    // decoded bytes the caller has not read yet
+    out: Vec<u8>,
+    read: usize,
+}
+
+impl<R: BufRead> GzDecoder<R> {
+    pub fn new(inner: R) -> GzDecoder<R> {
+        GzDecoder {
+            input: BitReader {
+                inner,
+                bits: 0,
+                count: 0,
+            },
+            state: State::Header,
+            members: 0,
+            window: vec![0; WINDOW],
+            written: 0,
+            crc: 0,
+            out: Vec::new(),
+            read: 0,
+        }
+    }
+
+    fn put(&mut self, byte: u8) {
+        self.window[self.written % WINDOW] = byte;
+        self.written += 1;
+        self.out.push(byte);
+    }
+
+    // Decodes until there is output for the caller or the input is done.
+    fn fill(&mut self) -> io::Result<()> {
+        self.out.clear();
+        self.read = 0;
+        while self.out.is_empty() {
+            self.state = match std::mem::replace(&mut self.state, State::Done) {
+                State::Header => {
+                    // gzip files may be concatenated; more input means another member
+                    if !self.header()? {
+                        return Ok(());
+                    }
+                    State::Block { last: false }
+                }
+                State::Block { last: true } => {
+                    self.trailer()?;
+                    State::Header
+                }
+                State::Block { last: false } => self.block()?,
+                State::Stored { left, last } => {
+                    let n = left.min(CHUNK);
+                    for _ in 0..n {
+                        let byte = self.input.byte()?;
+                        self.put(byte);
+                    }
+                    match left - n {
+                        0 => State::Block { last },
+                        left => State::Stored { left, last },
+                    }
+                }
+                State::Codes { lit, dist, last } => {
+                    if self.codes(&lit, &dist)? {
+                        State::Block { last }
+                    } else {
+                        State::Codes { lit, dist, last }
+                    }
+                }
+                State::Done => return Ok(()),
+            };
+        }
+        self.crc = crc32(self.crc, &self.out);
+        Ok(())
+    }
+
+    // Reads a member header; false at the end of the input.
+    fn header(&mut self) -> io::Result<bool> {
+        self.input.align();
+        let Some(id1) = self.input.byte_or_eof()? else {
+            if self.members == 0 {
+                return Err(invalid("empty file"));
+            }
+            return Ok(false);
+        };
+        if [id1, self.input.byte()?] != MAGIC {
+            return Err(invalid("not in gzip format"));
+        }
+        if self.input.byte()? != 8 {
+            return Err(invalid("unknown compression method"));
+        }
+        let flags = self.input.byte()?;
+        if flags & 0xe0 != 0 {
+            return Err(invalid("reserved flags set"));
+        }
+        // modification time, extra flags and operating system
+        for _ in 0..6 {
+            self.input.byte()?;
+        }
+        if flags & 0x04 != 0 {
+            let len = self.input.u16_le()?;
+            for _ in 0..len {
+                self.input.byte()?;
+            }
+        }
+        // original file name, then comment, both zero-terminated
+        for flag in [0x08, 0x10] {
+            if flags & flag != 0 {
+                while self.input.byte()? != 0 {}
+            }
+        }
+        if flags & 0x02 != 0 {
+            self.input.u16_le()?;
+        }
+        self.members += 1;
+        self.written = 0;
+        self.crc = 0;
+        Ok(true)
+    }
+
+    fn trailer(&mut self) -> io::Result<()> {
+        self.input.align();
+        let crc = self.input.u32_le()?;
+        let size = self.input.u32_le()?;
+        if crc != self.crc {
+            return Err(invalid("CRC mismatch"));
+        }
+        if size != self.written as u32 {
+            return Err(invalid("length mismatch"));
+        }
+        Ok(())
+    }
+
+    fn block(&mut self) -> io::Result<State> {
+        let last = self.input.bits(1)? == 1;
+        Ok(match self.input.bits(2)? {
+            0 => {
+                self.input.align();
+                let len = self.input.u16_le()?;
+                if self.input.u16_le()? != !len {
+                    return Err(invalid("stored block length mismatch"));
+                }
+                State::Stored {
+                    left: len as usize,
+                    last,
+                }
+            }
+            1 => {
+                let mut lengths = [8u8; 288];
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                State::Codes {
+                    lit: Huffman::new(&lengths)?,
+                    dist: Huffman::new(&[5; 30])?,
+                    last,
+                }
+            }
+            2 => {
+                let (lit, dist) = self.dynamic_codes()?;
+                State::Codes { lit, dist, last }
+            }
+            _ => return Err(invalid("reserved block type")),
+        })
+    }
+
+    fn dynamic_codes(&mut self) -> io::Result<(Huffman, Huffman)> {
+        let nlen = self.input.bits(5)? as usize + 257;
+        let ndist = self.input.bits(5)? as usize + 1;
+        let ncode = self.input.bits(4)? as usize + 4;
+        if nlen > 286 || ndist > 30 {
+            return Err(invalid("too many length or distance codes"));
+        }
+        let mut clens = [0u8; 19];
+        for &i in &CLEN_ORDER[..ncode] {
+            clens[i] = self.input.bits(3)? as u8;
+        }
+        let clen = Huffman::new(&clens)?;
+
+        let mut lengths = Vec::with_capacity(nlen + ndist);
+        while lengths.len() < nlen + ndist {
+            let (len, repeat) = match clen.decode(&mut self.input)? {
+                len @ 0..=15 => (len as u8, 1),
+                16 => {
+                    let prev = *lengths
+                        .last()
+                        .ok_or_else(|| invalid("repeat with no length"))?;
+                    (prev, 3 + self.input.bits(2)?)
+                }
+                17 => (0, 3 + self.input.bits(3)?),
+                _ => (0, 11 + self.input.bits(7)?),
+            };
+            if lengths.len() + repeat as usize > nlen + ndist {
+                return Err(invalid("too many code lengths"));
+            }
+            lengths.extend(std::iter::repeat_n(len, repeat as usize));
+        }
+        if lengths[256] == 0 {
+            return Err(invalid("no end-of-block code"));
+        }
+        let lit = Huffman::new(&lengths[..nlen])?;
+        let dist = Huffman::new(&lengths[nlen..])?;
+        Ok((lit, dist))
+    }
+
+    // Decodes symbols until the end of the block (true) or until enough
+    // output is waiting (false).
+    fn codes(&mut self, lit: &Huffman, dist: &Huffman) -> io::Result<bool> {
+        while self.out.len() < CHUNK {
+            let symbol = lit.decode(&mut self.input)? as usize;
+            if symbol < 256 {
+                self.put(symbol as u8);
+                continue;
+            }
+            if symbol == 256 {
+                return Ok(true);
+            }
+            let symbol = symbol - 257;
+            if symbol >= LENGTH_BASE.len() {
+                return Err(invalid("bad length code"));
+            }
+            let len = LENGTH_BASE[symbol] as usize
+                + self.input.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+            let symbol = dist.decode(&mut self.input)? as usize;
+            if symbol >= DIST_BASE.len() {
+                return Err(invalid("bad distance code"));
+            }
+            let distance =
+                DIST_BASE[symbol] as usize + self.input.bits(DIST_EXTRA[symbol] as u32)? as usize;
+            if distance > self.written.min(WINDOW) {
+                return Err(invalid("distance too far back"));
+            }
+            // byte by byte, since a copy may overlap the bytes it produces
+            for _ in 0..len {
+                let byte = self.window[(self.written - distance) % WINDOW];
+                self.put(byte);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl<R: BufRead> Read for GzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.read == self.out.len() {
+            self.fill()?;
+        }
+        let n = buf.len().min(self.out.len() - self.read);
+        buf[..n].copy_from_slice(&self.out[self.read..self.read + n]);
+        self.read += n;
+        Ok(n)
+    }
+}
+
+// With -z, wraps `reader` in a decoder if its data starts like gzip;
+// anything else is passed through as it is.
+pub fn maybe_decompress<'a, R: BufRead + 'a>(
+    decompress: bool,
+    mut reader: R,
+) -> io::Result<Box<dyn BufRead + 'a>> {
+    if decompress && reader.fill_buf()?.starts_with(&MAGIC) {
+        Ok(Box::new(io::BufReader::new(GzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn gunzip(data: &[u8]) -> io::Result<String> {
+        let mut text = String::new();
+        GzDecoder::new(data).read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    // from Python's gzip.compress with mtime=0
+    const FIXED: &str =
+        "1f8b0800000000000203cb48cdc9c95748afca2ce02a4e4dcecf4b51c8c9cc4be50200637866eb17000000";
+    const STORED: &str = "1f8b0800000000000403010700f8ff73746f7265640ae29c53a507000000";
+    const DYNAMIC: &str = "1f8b080000000000020315c8410100300cc2c0ff54602dd0fab7304a5e1c188d0222614ca14b5b46f78a39f6e67de97c732630000000";
+
+    #[test]
+    fn inflates_every_block_type() {
+        assert_eq!("hello gzip\nsecond line\n", gunzip(&hex(FIXED)).unwrap());
+        assert_eq!("stored\n", gunzip(&hex(STORED)).unwrap());
+        assert_eq!(
+            "aaba d caa accadbaabaaaacacaead abaadbacaababec\n",
+            gunzip(&hex(DYNAMIC)).unwrap()
+        );
+    }
+
+    #[test]
+    fn members_and_header_fields() {
+        // concatenated members decompress to the concatenated data
+        let both = [hex(STORED), hex(FIXED)].concat();
+        assert_eq!("stored\nhello gzip\nsecond line\n", gunzip(&both).unwrap());
+        // the same stored member with a file name in its header
+        let mut named = hex(STORED);
+        named[3] = 0x08;
+        named.splice(10..10, b"stored.txt\0".iter().copied());
+        assert_eq!("stored\n", gunzip(&named).unwrap());
+    }
+
+    #[test]
+    fn output_longer_than_the_window() {
+        // a stored block of "abc\n", then a fixed block copying it from 4
+        // bytes back over and over, 258 bytes at a time
+        let mut data = hex("1f8b08000000000000ff");
+        data.extend([0x00, 0x04, 0x00, 0xfb, 0xff]);
+        data.extend(b"abc\n");
+        // BFINAL=1, BTYPE=01, then length 258 (code 285) at distance 4
+        // (code 3) 400 times, and end of block
+        let mut bits: Vec<(u32, u32)> = vec![(1, 1), (1, 2)];
+        for _ in 0..400 {
+            // 285 is the 8-bit code 0b11000101, sent most significant first
+            bits.push((0b1010_0011, 8));
+            bits.push((0b11000, 5));
+        }
+        bits.push((0, 7));
+        let (mut acc, mut n) = (0u64, 0);
+        for (value, len) in bits {
+            acc |= (value as u64) << n;
+            n += len;
+            while n >= 8 {
+                data.push(acc as u8);
+                acc >>= 8;
+                n -= 8;
+            }
+        }
+        data.push(acc as u8);
+        let expected = "abc\n".repeat(1 + 400 * 258 / 4);
+        data.extend(crc32(0, expected.as_bytes()).to_le_bytes());
+        data.extend((expected.len() as u32).to_le_bytes());
+        assert_eq!(expected, gunzip(&data).unwrap());
+    }
+
+    #[test]
+    fn corrupt_data_is_an_error() {
+        let mut data = hex(FIXED);
+        let crc = data.len() - 8;
+        data[crc] ^= 1;
+        let err = gunzip(&data).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert_eq!("invalid gzip data: CRC mismatch", err.to_string());
+        let truncated = hex(FIXED);
+        assert!(gunzip(&truncated[..20]).is_err());
+        assert!(gunzip(b"plain text").is_err());
+    }
+
+    #[test]
+    fn plain_input_passes_through() {
+        let mut text = String::new();
+        let mut reader = maybe_decompress(true, &b"plain\n"[..]).unwrap();
+        reader.read_to_string(&mut text).unwrap();
+        assert_eq!("plain\n", text);
+        let stored = hex(STORED);
+        let mut reader = maybe_decompress(true, &stored[..]).unwrap();
+        text.clear();
+        reader.read_to_string(&mut text).unwrap();
+        assert_eq!("stored\n", text);
+    }
+}
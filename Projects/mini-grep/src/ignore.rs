@@ -0,0 +1,141 @@
+// The rules of one `.gitignore` or `.ignore` file.
+//
+// Each line is a glob. A pattern containing a '/' before its end is anchored
+// to the directory holding the file; any other pattern matches a name at any
+// depth below it. A trailing '/' limits a rule to directories, a leading '!'
+// re-includes what an earlier rule excluded, and the last matching rule wins.
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::glob::Glob;
+
+#[derive(Debug, Clone)]
+struct Rule {
+    glob: Glob,
+    negated: bool,
+    dir_only: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Ignore {
+    rules: Vec<Rule>,
+}
+
+impl Ignore {
+    pub fn parse(contents: &str) -> Ignore {
+        let mut ignore = Ignore::default();
+        ignore.add(contents);
+        ignore
+    }
+
+    // Reads the ignore files of `dir`. Rules from `.ignore` come after those
+    // from `.gitignore`, so they take precedence.
+    pub fn from_dir(dir: &Path) -> io::Result<Ignore> {
+        let mut ignore = Ignore::default();
+        for name in [".gitignore", ".ignore"] {
+            match fs::read_to_string(dir.join(name)) {
+                Ok(contents) => ignore.add(&contents),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(ignore)
+    }
+
+    fn add(&mut self, contents: &str) {
+        for line in contents.lines() {
+            if let Some(rule) = parse_rule(line) {
+                self.rules.push(rule);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // `Some(true)` if the last rule matching `path` (relative to the ignore
+    // file's directory, with '/' separators) ignores it, `Some(false)` if it
+    // re-includes it, and `None` if no rule matches.
+    pub fn matched(&self, path: &str, is_dir: bool) -> Option<bool> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && rule.glob.is_match(path))
+            .map(|rule| !rule.negated)
+    }
+}
+
+fn parse_rule(line: &str) -> Option<Rule> {
+    // trailing spaces are dropped unless escaped with a backslash
+    let mut line = line.trim_end_matches('\r');
+    while line.ends_with(' ') && !line.ends_with("\\ ") {
+        line = &line[..line.len() - 1];
+    }
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let (negated, line) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let (dir_only, line) = match line.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    if line.is_empty() {
+        return None;
+    }
+    let glob = match line.strip_prefix('/') {
+        Some(anchored) => Glob::new(anchored),
+        None if line.contains('/') => Glob::new(line),
+        None => Glob::new(&format!("**/{line}")),
+    };
+    Some(Rule {
+        glob,
+        negated,
+        dir_only,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_match_at_any_depth() {
+        let ignore = Ignore::parse("# build output\ntarget/\n*.log\n\n");
+        assert_eq!(Some(true), ignore.matched("target", true));
+        assert_eq!(Some(true), ignore.matched("nested/target", true));
+        // `target/` only applies to directories
+        assert_eq!(None, ignore.matched("target", false));
+        assert_eq!(Some(true), ignore.matched("logs/today.log", false));
+        assert_eq!(None, ignore.matched("src/main.rs", false));
+    }
+
+    #[test]
+    fn slashes_anchor_patterns() {
+        let ignore = Ignore::parse("/build\ndocs/*.html\n");
+        assert_eq!(Some(true), ignore.matched("build", true));
+        assert_eq!(None, ignore.matched("src/build", true));
+        assert_eq!(Some(true), ignore.matched("docs/index.html", false));
+        assert_eq!(None, ignore.matched("src/docs/index.html", false));
+    }
+
+    #[test]
+    fn last_matching_rule_wins() {
+        let ignore = Ignore::parse("*.txt\n!keep.txt\n");
+        assert_eq!(Some(true), ignore.matched("notes.txt", false));
+        assert_eq!(Some(false), ignore.matched("keep.txt", false));
+        let ignore = Ignore::parse("!keep.txt\n*.txt\n");
+        assert_eq!(Some(true), ignore.matched("keep.txt", false));
+    }
+
+    #[test]
+    fn escapes_and_trailing_spaces() {
+        let ignore = Ignore::parse("\\#notes  \n\\!important\n");
+        assert_eq!(Some(true), ignore.matched("#notes", false));
+        assert_eq!(Some(true), ignore.matched("!important", false));
+    }
+}
@@ -0,0 +1,51 @@
+// Just enough JSON for `--json` output, written by hand to keep the crate
+// free of dependencies.
+use std::fmt::{self, Display, Write};
+
+// A string formatted as a quoted JSON string literal.
+pub struct JsonStr<'a>(pub &'a str);
+
+impl Display for JsonStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        // copy runs of plain characters in one go
+        let mut plain = 0;
+        for (i, c) in self.0.char_indices() {
+            let escaped = match c {
+                '"' => "\\\"",
+                '\\' => "\\\\",
+                '\n' => "\\n",
+                '\r' => "\\r",
+                '\t' => "\\t",
+                c if c < ' ' || c == '\u{7f}' => "",
+                _ => continue,
+            };
+            f.write_str(&self.0[plain..i])?;
+            if escaped.is_empty() {
+                write!(f, "\\u{:04x}", c as u32)?;
+            } else {
+                f.write_str(escaped)?;
+            }
+            plain = i + c.len_utf8();
+        }
+        f.write_str(&self.0[plain..])?;
+        f.write_char('"')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_special_characters() {
+        assert_eq!(r#""plain""#, JsonStr("plain").to_string());
+        assert_eq!(r#""say \"hi\"\\n""#, JsonStr("say \"hi\"\\n").to_string());
+        assert_eq!(r#""a\tb\r\n""#, JsonStr("a\tb\r\n").to_string());
+        assert_eq!(
+            r#""bell\u0007 del\u007f""#,
+            JsonStr("bell\u{7} del\u{7f}").to_string()
+        );
+        assert_eq!(r#""ünïcödé""#, JsonStr("ünïcödé").to_string());
+    }
+}
@@ -1,70 +1,450 @@
-use std::error::Error;
-use std::{env, fs};
-pub struct Config {
-    pub query: String,
-    pub file_path: String,
-    pub ignore_case: bool,
-}
-impl Config {
-    pub fn build(args: &[String]) -> Result<Config, &'static str> {
-        if args.len() < 3 {
-            return Err("Error, no enough arguments, should use: \nminigrep pattern file_path");
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::ops::Range;
+
+pub mod aho_corasick;
+pub mod config;
+pub mod error;
+pub mod fold;
+mod follow;
+pub mod fuzzy;
+pub mod glob;
+pub mod gzip;
+pub mod ignore;
+pub mod json;
+pub mod memchr;
+pub mod mmap;
+pub mod parallel;
+pub mod printer;
+pub mod regex;
+pub mod replace;
+pub mod searcher;
+#[cfg(test)]
+mod tempdir;
+pub mod tui;
+pub mod walk;
+
+pub use aho_corasick::AhoCorasick;
+pub use config::{ColorChoice, Config, OutputMode};
+pub use error::{GrepError, Outcome};
+pub use fold::FoldedLiteral;
+pub use fuzzy::Fuzzy;
+pub use glob::Glob;
+pub use ignore::Ignore;
+pub use memchr::Finder;
+pub use mmap::Mmap;
+pub use printer::{Printer, Stats};
+pub use regex::Regex;
+pub use searcher::{Matcher, Searcher, Sink};
+pub use walk::Walk;
+
+// Searches every path in the config. Problems with single files inside a
+// directory are reported on stderr and recorded in the outcome; anything
+// else stops the run with an error.
+pub fn run(config: Config) -> Result<Outcome, GrepError> {
+    if config.interactive {
+        return tui::run(&config);
+    }
+    let pattern = Pattern::new(&config)?;
+    let color = config.color.enabled(io::stdout().is_terminal());
+    if config.follow {
+        return follow::run(&config, &pattern, color);
+    }
+    let mut printer = Printer::new(&config, io::stdout().lock()).color(color);
+    // feat: files are searched in parallel, output stays in order
+    parallel::search_all(&config, &pattern, &mut printer, color)
+}
+
+// One matching line. `byte_offset` is where the line starts in the searched
+// contents and `span` is the byte range of the match within `line`. With -U
+// a match may cover several lines; `line` then holds all of them, joined
+// with "\n".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match<'a> {
+    pub line_number: usize,
+    pub byte_offset: usize,
+    // with --fuzzy, how many edits the match is away from the pattern
+    pub distance: Option<usize>,
+    pub span: Range<usize>,
+    pub line: &'a str,
+}
+
+impl Match<'_> {
+    // 1-based column of the match, counted in bytes like grep and ripgrep
+    pub fn column(&self) -> usize {
+        self.span.start + 1
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.line[self.span.clone()]
+    }
+
+    // The number of the last line the match covers.
+    pub fn end_line_number(&self) -> usize {
+        self.line_number + memchr::count(b'\n', self.line.as_bytes())
+    }
+}
+
+// `line` with each of `spans` (in order, not overlapping) replaced.
+pub fn replace_all(line: &str, spans: &[Range<usize>], replacement: &str) -> String {
+    let mut replaced = String::with_capacity(line.len());
+    let mut at = 0;
+    for span in spans {
+        replaced.push_str(&line[at..span.start]);
+        replaced.push_str(replacement);
+        at = span.end;
+    }
+    replaced.push_str(&line[at..]);
+    replaced
+}
+
+// Splits contents into lines like `str::lines`, also yielding the byte offset
+// at which each line starts.
+fn lines_with_offsets(contents: &str) -> impl Iterator<Item = (usize, &str)> {
+    contents.split_inclusive('\n').scan(0, |offset, raw| {
+        let start = *offset;
+        *offset += raw.len();
+        Some((start, trim_line_ending(raw)))
+    })
+}
+
+fn trim_line_ending(line: &str) -> &str {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+// the patterns as selected by the config, compiled once per run
+struct Pattern {
+    kind: Kind,
+    boundary: Boundary,
+}
+
+enum Kind {
+    Literal(Finder),
+    CaseInsensitive(FoldedLiteral),
+    // feat: any number of literals, matched in a single pass
+    // with the boundary, which may rule out the longest of several
+    // patterns at one position but not a shorter one
+    Multi {
+        ac: AhoCorasick,
+        boundary: Boundary,
+    },
+    Regex(Regex),
+    // feat: --fuzzy, the closest match within a number of edits
+    Fuzzy {
+        patterns: Vec<Fuzzy>,
+        boundary: Boundary,
+    },
+}
+
+// What has to surround a match for it to count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Boundary {
+    None,
+    // feat: -w, no word character directly before or after the match
+    Word,
+    // feat: -x, the match is the whole line
+    Line,
+}
+
+impl Boundary {
+    fn accepts(self, line: &str, span: &Range<usize>) -> bool {
+        self.accepts_start(line, span.start) && self.accepts_end(line, span.end)
+    }
+
+    // `line` is several lines with -U, so a line can also start after a
+    // newline and end before one.
+    fn accepts_start(self, line: &str, start: usize) -> bool {
+        match self {
+            Boundary::None => true,
+            Boundary::Word => !is_word(line[..start].chars().next_back()),
+            Boundary::Line => start == 0 || line[..start].ends_with('\n'),
+        }
+    }
+
+    fn accepts_end(self, line: &str, end: usize) -> bool {
+        match self {
+            Boundary::None => true,
+            Boundary::Word => !is_word(line[end..].chars().next()),
+            Boundary::Line => end == line.len() || line[end..].starts_with('\n'),
+        }
+    }
+}
+
+fn is_word(c: Option<char>) -> bool {
+    c.is_some_and(regex::is_word_char)
+}
+
+impl Pattern {
+    fn new(config: &Config) -> Result<Pattern, GrepError> {
+        let boundary = if config.line_regexp {
+            Boundary::Line
+        } else if config.word_regexp {
+            Boundary::Word
+        } else {
+            Boundary::None
+        };
+        let kind = Kind::new(config, boundary)?;
+        Ok(Pattern { kind, boundary })
+    }
+
+    // How far the match at `span` is from the closest pattern, for --fuzzy.
+    fn distance(&self, line: &str, span: &Range<usize>) -> Option<usize> {
+        let Kind::Fuzzy { patterns, .. } = &self.kind else {
+            return None;
+        };
+        let text = &line[span.clone()];
+        patterns.iter().map(|fuzzy| fuzzy.distance(text)).min()
+    }
+
+    fn find(&self, line: &str) -> Option<Range<usize>> {
+        self.find_at(line, 0)
+    }
+
+    // The first match that starts at or after byte `start` of `line` and
+    // has the required boundaries.
+    fn find_at(&self, line: &str, mut start: usize) -> Option<Range<usize>> {
+        loop {
+            let span = self.kind.find_at(line, start)?;
+            if self.boundary.accepts(line, &span) {
+                return Some(span);
+            }
+            // "duct" in "productive duct": try again after the 'd'
+            let skipped = line[span.start..].chars().next()?;
+            start = span.start + skipped.len_utf8();
         }
-        let query = args[1].clone();
-        let file_path = args[2].clone();
-
-        // feat: add env for case sensitive
-        let ignore_case = env::var("IGNORE_CASE").is_ok();
-        Ok(Config {
-            query,
-            file_path,
-            ignore_case,
-        })
     }
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents =
-        fs::read_to_string(config.file_path).expect("Should have been able to read the file");
-    let results = if config.ignore_case {
-        search_case_insensitive(&config.query, &contents)
-    } else {
-        search(&config.query, &contents)
-    };
-    
-    for line in results {
-        println!("{line}");
+// Lines reach the pattern through the `Matcher` trait, so the command line
+// searches with the same `Searcher` as library users do.
+impl Matcher for Pattern {
+    fn find_at(&self, haystack: &[u8], start: usize) -> Option<Range<usize>> {
+        Pattern::find_at(self, str::from_utf8(haystack).ok()?, start)
+    }
+
+    fn find_str_at(&self, haystack: &str, start: usize) -> Option<Range<usize>> {
+        Pattern::find_at(self, haystack, start)
+    }
+
+    fn literal(&self) -> Option<&Finder> {
+        match &self.kind {
+            Kind::Literal(finder) => Some(finder),
+            _ => None,
+        }
+    }
+
+    fn distance(&self, haystack: &[u8], span: &Range<usize>) -> Option<usize> {
+        Pattern::distance(self, str::from_utf8(haystack).ok()?, span)
     }
-    Ok(())
 }
 
-// public core function: search for minigrep
-pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    let mut result: Vec<&str> = Vec::new();
-    for line in contents.lines() {
-        if line.contains(query) {
-            result.push(line);
+impl Kind {
+    fn new(config: &Config, boundary: Boundary) -> Result<Kind, GrepError> {
+        let mut patterns = config.patterns.clone();
+        for path in &config.pattern_files {
+            let contents = fs::read_to_string(path).map_err(|e| GrepError::io(path, e))?;
+            patterns.extend(contents.lines().map(String::from));
+        }
+
+        if config.use_regex && !patterns.is_empty() {
+            // compile each pattern on its own first so errors point into it
+            for pattern in &patterns {
+                Regex::new(pattern)?;
+            }
+            let alternation: Vec<String> = patterns.iter().map(|p| format!("(?:{p})")).collect();
+            // anchor the whole alternation, so `a|ab` can still match "ab"
+            let mut regex = alternation.join("|");
+            if config.line_regexp {
+                regex = format!("^(?:{regex})$");
+            }
+            let re = Regex::builder(&regex)
+                .case_insensitive(config.ignore_case)
+                .multi_line(config.multiline)
+                .whole_words(config.word_regexp && !config.line_regexp)
+                .build()?;
+            return Ok(Kind::Regex(re));
+        }
+        if let Some(max_distance) = config.fuzzy {
+            let patterns = patterns
+                .iter()
+                .map(|pattern| {
+                    Fuzzy::new(pattern, max_distance, config.ignore_case).ok_or_else(|| {
+                        GrepError::Usage(format!(
+                            "--fuzzy patterns can be at most {} characters long",
+                            fuzzy::MAX_LEN
+                        ))
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+            return Ok(Kind::Fuzzy { patterns, boundary });
+        }
+        Ok(match patterns.as_slice() {
+            [pattern] if config.ignore_case => Kind::CaseInsensitive(FoldedLiteral::new(pattern)),
+            [pattern] => Kind::Literal(Finder::new(pattern.as_bytes())),
+            // no patterns at all (say, an empty -f file) select nothing
+            _ => Kind::Multi {
+                ac: AhoCorasick::new(&patterns, config.ignore_case),
+                boundary,
+            },
+        })
+    }
+
+    // The first match that starts at or after byte `start` of `line`.
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        let rest = &line[start..];
+        let shift = |span: Range<usize>| span.start + start..span.end + start;
+        match self {
+            Kind::Literal(finder) => finder
+                .find(rest.as_bytes())
+                .map(|i| shift(i..i + finder.needle().len())),
+            Kind::CaseInsensitive(literal) => literal.find(rest).map(shift),
+            Kind::Multi { ac, boundary } => ac
+                .find_where(rest, |span| boundary.accepts(line, &shift(span.clone())))
+                .map(shift),
+            // the regex sees the whole line, so `^` and `\b` still work
+            Kind::Regex(re) => re.find_at(line.as_bytes(), start),
+            // the fewest edits wins, then the leftmost match
+            Kind::Fuzzy {
+                patterns,
+                boundary: Boundary::None,
+            } => patterns
+                .iter()
+                .filter_map(|fuzzy| fuzzy.find(rest))
+                .min_by_key(|(span, distance)| (*distance, span.start))
+                .map(|(span, _)| shift(span)),
+            Kind::Fuzzy { patterns, boundary } => {
+                find_fuzzy_bounded(patterns, *boundary, line, start)
+            }
         }
     }
-    result
 }
 
-// public core function: search while case sensitive
-pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    let query = query.to_lowercase();
-    let mut results = Vec::new();
-    for line in contents.lines() {
-        if line.to_lowercase().contains(&query) {
-            results.push(line);
+// The closest fuzzy match with the boundaries of -w or -x. The closest part
+// of "the recieve path" to "receive" may be "recie", which is no word, so
+// the match is chosen from all that start and end on a boundary. One bitap
+// pass finds where matches end and how close the closest one there is; only
+// at the ends on a boundary that could beat the best so far are the starts
+// worked out.
+fn find_fuzzy_bounded(
+    patterns: &[Fuzzy],
+    boundary: Boundary,
+    line: &str,
+    start: usize,
+) -> Option<Range<usize>> {
+    let mut scratch = fuzzy::Scratch::default();
+    // the fewest edits wins, then the leftmost match, then the first pattern
+    // and the shortest match
+    let mut best: Option<(usize, usize, Range<usize>)> = None;
+    for (i, fuzzy) in patterns.iter().enumerate() {
+        for (end, fewest) in fuzzy.ends(&line[start..]) {
+            let end = start + end;
+            if best.as_ref().is_some_and(|(d, _, _)| fewest > *d)
+                || !boundary.accepts_end(line, end)
+            {
+                continue;
+            }
+            let found = fuzzy
+                .starts(&line[start..end], &mut scratch)
+                .map(|(s, distance)| (distance, start + s))
+                .filter(|&(_, s)| boundary.accepts_start(line, s))
+                .min();
+            let Some((distance, s)) = found else {
+                continue;
+            };
+            let better = best.as_ref().is_none_or(|(d, first, span)| {
+                (distance, s, i, end) < (*d, span.start, *first, span.end)
+            });
+            if better {
+                best = Some((distance, i, s..end));
+            }
         }
     }
+    best.map(|(_, _, span)| span)
+}
+
+fn find_literal(query: &str, line: &str) -> Option<Range<usize>> {
+    line.find(query).map(|start| start..start + query.len())
+}
+
+// Whether a line is selected, given its match: with `invert` the lines that
+// did not match are selected instead, with an empty span at column 1.
+fn select(found: Option<Range<usize>>, invert: bool) -> Option<Range<usize>> {
+    match (found, invert) {
+        (Some(span), false) => Some(span),
+        (None, true) => Some(0..0),
+        _ => None,
+    }
+}
+
+// Collects a `Match` for every selected line.
+fn search_lines<'a>(
+    contents: &'a str,
+    invert: bool,
+    find: impl Fn(&str) -> Option<Range<usize>>,
+) -> Vec<Match<'a>> {
+    let mut results = Vec::new();
+    for (index, (byte_offset, line)) in lines_with_offsets(contents).enumerate() {
+        let Some(span) = select(find(line), invert) else {
+            continue;
+        };
+        results.push(Match {
+            line_number: index + 1,
+            byte_offset,
+            distance: None,
+            span,
+            line,
+        });
+    }
     results
 }
 
+// public core function: search for minigrep
+pub fn search<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
+    search_lines(contents, false, |line| find_literal(query, line))
+}
+
+// The same as `search`, but looks for `query` in all of `contents` at once
+// and only splits off the lines it is found in, which is much faster when
+// most lines do not match (see `benches/search.rs`).
+pub fn search_fast<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
+    let finder = Finder::new(query.as_bytes());
+    searcher::CandidateLines::new(&finder, contents.as_bytes())
+        .filter_map(|(line_number, byte_offset, raw)| {
+            let line = trim_line_ending(&contents[byte_offset..byte_offset + raw.len()]);
+            let span = find_literal(query, line)?;
+            Some(Match {
+                line_number,
+                byte_offset,
+                distance: None,
+                span,
+                line,
+            })
+        })
+        .collect()
+}
+
+// public core function: search while case sensitive
+pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
+    let literal = FoldedLiteral::new(query);
+    search_lines(contents, false, |line| literal.find(line))
+}
+
+// public core function: search with a compiled regular expression
+pub fn search_regex<'a>(re: &Regex, contents: &'a str) -> Vec<Match<'a>> {
+    search_lines(contents, false, |line| re.find(line))
+}
+
 // test module
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::path::Path;
+
+    fn lines<'a>(matches: Vec<Match<'a>>) -> Vec<&'a str> {
+        matches.into_iter().map(|m| m.line).collect()
+    }
+
     #[test]
     fn one_result() {
         let query = "duct";
@@ -72,7 +452,10 @@ mod tests {
 Rust:
 safe, fast, productive.
 Pick three.";
-        assert_eq!(vec!["safe, fast, productive."], search(query, contents));
+        assert_eq!(
+            vec!["safe, fast, productive."],
+            lines(search(query, contents))
+        );
     }
 
     #[test]
@@ -83,7 +466,10 @@ Rust:
 safe, fast, productive.
 Pick three.
 Duct tape.";
-        assert_eq!(vec!["safe, fast, productive."], search(query, contents));
+        assert_eq!(
+            vec!["safe, fast, productive."],
+            lines(search(query, contents))
+        );
     }
     #[test]
     fn case_insensitive() {
@@ -95,7 +481,346 @@ Pick three.
 Trust me.";
         assert_eq!(
             vec!["Rust:", "Trust me."],
-            search_case_insensitive(query, contents)
+            lines(search_case_insensitive(query, contents))
+        );
+    }
+
+    #[test]
+    fn regex_search() {
+        let re = Regex::new(r"fn \w+\(").unwrap();
+        let contents = "\
+pub fn main() {
+    let f = fn_ptr;
+    helper(fn_ptr);
+}
+fn helper(x: u8) {}";
+        assert_eq!(
+            vec!["pub fn main() {", "fn helper(x: u8) {}"],
+            lines(search_regex(&re, contents))
         );
     }
+
+    #[test]
+    fn match_positions() {
+        let contents = "Rust:\r\nsafe, fast, productive.\nPick three.";
+        let m = &search("duct", contents)[0];
+        assert_eq!(2, m.line_number);
+        assert_eq!(7, m.byte_offset);
+        assert_eq!(15..19, m.span);
+        assert_eq!(16, m.column());
+        assert_eq!("duct", m.as_str());
+    }
+
+    #[test]
+    fn fast_search_agrees_with_search() {
+        let contents = "one duct\r\ntwo\n\nduct, duct\nthree\nlast duct";
+        for query in ["duct", "t\nd", "", "\r", "absent"] {
+            assert_eq!(
+                search(query, contents),
+                search_fast(query, contents),
+                "{query:?}"
+            );
+        }
+        let lines: Vec<_> = search_fast("duct", contents)
+            .iter()
+            .map(|m| m.line_number)
+            .collect();
+        assert_eq!(vec![1, 4, 6], lines);
+    }
+
+    #[test]
+    fn case_insensitive_span_maps_to_original_line() {
+        // 'İ' lowercases to two chars, shifting every later byte
+        let m = &search_case_insensitive("rust", "İ love RUST")[0];
+        assert_eq!("RUST", m.as_str());
+    }
+
+    fn search_stream(config: &Config, input: &[u8]) -> io::Result<String> {
+        let pattern = Pattern::new(config).unwrap();
+        let searcher = Searcher::from(config);
+        let mut printer = Printer::new(config, Vec::new());
+        printer.begin(Path::new("-"));
+        searcher.search_reader(&pattern, io::Cursor::new(input), &mut printer)?;
+        let output = String::from_utf8(printer.into_inner()).unwrap();
+        // the whole-buffer fast path must not change a thing
+        let mut printer = Printer::new(config, Vec::new());
+        printer.begin(Path::new("-"));
+        searcher.search_slice(&pattern, input, &mut printer)?;
+        assert_eq!(output, String::from_utf8(printer.into_inner()).unwrap());
+        Ok(output)
+    }
+
+    #[test]
+    fn streaming_search() {
+        let config = Config {
+            patterns: vec![String::from("duct")],
+            line_number: true,
+            ..Config::default()
+        };
+        let input = b"Rust:\r\nsafe, fast, productive.\nPick three.\nDuct tape.";
+        assert_eq!(
+            "2:safe, fast, productive.\n",
+            search_stream(&config, input).unwrap()
+        );
+        let config = Config {
+            output: OutputMode::Count,
+            invert_match: true,
+            ..config
+        };
+        assert_eq!("3\n", search_stream(&config, input).unwrap());
+    }
+
+    #[test]
+    fn file_list_modes() {
+        let mut config = Config {
+            patterns: vec![String::from("duct")],
+            output: OutputMode::FilesWithMatches,
+            ..Config::default()
+        };
+        let input = b"productive\nproduct\n";
+        assert_eq!("-\n", search_stream(&config, input).unwrap());
+        assert_eq!("", search_stream(&config, b"nothing").unwrap());
+        config.output = OutputMode::FilesWithoutMatch;
+        assert_eq!("", search_stream(&config, input).unwrap());
+        assert_eq!("-\n", search_stream(&config, b"nothing").unwrap());
+        config.output = OutputMode::Quiet;
+        assert_eq!("", search_stream(&config, input).unwrap());
+    }
+
+    #[test]
+    fn multiple_patterns() {
+        let mut config = Config {
+            patterns: vec![String::from("fast"), String::from("tape")],
+            line_number: true,
+            ..Config::default()
+        };
+        let input = b"Rust:\nsafe, fast, productive.\nPick three.\nDuct tape.";
+        let expected = "2:safe, fast, productive.\n4:Duct tape.\n";
+        assert_eq!(expected, search_stream(&config, input).unwrap());
+        config.use_regex = true;
+        assert_eq!(expected, search_stream(&config, input).unwrap());
+        config.patterns.clear();
+        assert_eq!("", search_stream(&config, input).unwrap());
+    }
+
+    #[test]
+    fn every_match_on_a_line_is_reported() {
+        let mut config = Config {
+            patterns: vec![String::from("ab")],
+            json: true,
+            ..Config::default()
+        };
+        let input = b"ab ab\nnone\nxab\n";
+        let output = search_stream(&config, input).unwrap();
+        let records: Vec<&str> = output.lines().collect();
+        assert_eq!(4, records.len());
+        assert!(records[1].ends_with(
+            r#""submatches":[{"match":"ab","start":0,"end":2},{"match":"ab","start":3,"end":5}]}"#
+        ));
+        assert!(records[2].contains(r#""line_number":3,"column":2,"absolute_offset":11,"#));
+        assert_eq!(
+            r#"{"type":"end","path":"-","matched_lines":2,"matches":3}"#,
+            records[3]
+        );
+        // -v selects lines without matches, so there is nothing to report
+        config.invert_match = true;
+        let output = search_stream(&config, input).unwrap();
+        assert!(output.contains(r#""line":"none","submatches":[]}"#));
+    }
+
+    #[test]
+    fn whole_words_and_lines() {
+        let input = "productive duct\nDuct_tape\nduct\n(duct)\n".as_bytes();
+        let mut config = Config {
+            patterns: vec![String::from("duct")],
+            line_number: true,
+            word_regexp: true,
+            ..Config::default()
+        };
+        // the second "duct" on line 1 is found after skipping the first
+        assert_eq!(
+            "1:productive duct\n3:duct\n4:(duct)\n",
+            search_stream(&config, input).unwrap()
+        );
+        config.ignore_case = true;
+        assert_eq!(
+            "1:productive duct\n3:duct\n4:(duct)\n",
+            search_stream(&config, input).unwrap()
+        );
+        config.line_regexp = true;
+        assert_eq!("3:duct\n", search_stream(&config, input).unwrap());
+        config.patterns = vec![String::from("d|duct"), String::from(r"\(\w+\)")];
+        config.use_regex = true;
+        assert_eq!("3:duct\n4:(duct)\n", search_stream(&config, input).unwrap());
+    }
+
+    #[test]
+    fn whole_words_try_every_alternative() {
+        let mut config = Config {
+            word_regexp: true,
+            use_regex: true,
+            ..Config::default()
+        };
+        for (pattern, line) in [
+            ("a|ab", "ab\n"),
+            ("ab|abc", "abc\n"),
+            ("fo|foo", "foo bar\n"),
+        ] {
+            config.patterns = vec![String::from(pattern)];
+            assert_eq!(line, search_stream(&config, line.as_bytes()).unwrap());
+        }
+        // and every literal, when the longest is not a whole word
+        config.use_regex = false;
+        config.patterns = vec![String::from("a"), String::from("a-b")];
+        assert_eq!("a-bc\n", search_stream(&config, b"a-bc\n").unwrap());
+    }
+
+    #[test]
+    fn word_boundaries_are_unicode_aware() {
+        let config = Config {
+            patterns: vec![String::from("ber")],
+            ignore_case: true,
+            word_regexp: true,
+            ..Config::default()
+        };
+        assert_eq!("", search_stream(&config, "über".as_bytes()).unwrap());
+        assert_eq!(
+            "é BER\n",
+            search_stream(&config, "é BER".as_bytes()).unwrap()
+        );
+    }
+
+    #[test]
+    fn fuzzy_matches_report_their_distance() {
+        let input = b"connection reset\nconection reset\ncollection\nunrelated\n";
+        let mut config = Config {
+            patterns: vec![String::from("connection")],
+            fuzzy: Some(1),
+            line_number: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            "1:0:connection reset\n2:1:conection reset\n",
+            search_stream(&config, input).unwrap()
+        );
+        // "recieve" is two edits from "receive", though "recie" is closer
+        config.patterns = vec![String::from("receive")];
+        config.fuzzy = Some(2);
+        config.word_regexp = true;
+        let output = search_stream(&config, b"the recieve path\n").unwrap();
+        assert_eq!("1:2:the recieve path\n", output);
+        config.patterns = vec![String::from("connection")];
+        config.word_regexp = false;
+        config.line_regexp = true;
+        assert_eq!("3:2:collection\n", search_stream(&config, input).unwrap());
+        // the location comes first, for editors
+        config.with_filename = true;
+        config.column = true;
+        assert_eq!(
+            "-:3:1:2:collection\n",
+            search_stream(&config, input).unwrap()
+        );
+        config.with_filename = false;
+        config.column = false;
+
+        config.line_regexp = false;
+        config.json = true;
+        let output = search_stream(&config, b"a conection\n").unwrap();
+        assert!(output.contains(r#""distance":1,"#), "{output}");
+        assert!(output.contains(r#"{"match":"conection","start":2,"end":11}"#));
+    }
+
+    #[test]
+    fn multiline_matches_cover_whole_lines() {
+        let input = b"fn add(\r\n    x: i32,\n) -> i32 {\n    x\n}\nfn one(x: i32) {}\n";
+        let mut config = Config {
+            patterns: vec![String::from(r"fn \w+\(\s+x")],
+            use_regex: true,
+            multiline: true,
+            line_number: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            "1:fn add(\n2:    x: i32,\n",
+            search_stream(&config, input).unwrap()
+        );
+        // ^ and $ match at every line
+        config.patterns = vec![String::from(r"^\}$")];
+        config.output = OutputMode::Count;
+        assert_eq!("1\n", search_stream(&config, input).unwrap());
+        // every line a match covers counts as selected
+        config.patterns = vec![String::from(r"i32,\n\)|i32 \{")];
+        assert_eq!("2\n", search_stream(&config, input).unwrap());
+        config.invert_match = true;
+        assert_eq!("4\n", search_stream(&config, input).unwrap());
+
+        config.output = OutputMode::Lines;
+        config.invert_match = false;
+        config.json = true;
+        config.patterns = vec![String::from(r"add\(\s+x")];
+        let output = search_stream(&config, input).unwrap();
+        assert!(output.contains(r#""line_number":1,"#), "{output}");
+        assert!(output.contains(r#""end_line_number":2,"#), "{output}");
+        assert!(output.contains(r#"{"match":"add(\n    x","start":3,"end":13}"#));
+    }
+
+    #[test]
+    fn invalid_utf8_is_searched_lossily() {
+        let config = Config {
+            patterns: vec![String::from("caf")],
+            line_number: true,
+            ..Config::default()
+        };
+        // "café" in Latin-1
+        let output = search_stream(&config, b"ok\ncaf\xe9 au lait\n").unwrap();
+        assert_eq!("2:caf\u{FFFD} au lait\n", output);
+    }
+
+    #[test]
+    fn binary_files_report_a_match() {
+        let mut config = Config {
+            patterns: vec![String::from("duct")],
+            ..Config::default()
+        };
+        let input = b"\x7fELF\0\x01\nproduct\nduct\n";
+        assert_eq!(
+            "Binary file - matches\n",
+            search_stream(&config, input).unwrap()
+        );
+        assert_eq!("", search_stream(&config, b"bin\0\nnothing\n").unwrap());
+        // a NUL after the first block still counts once it is read
+        let late = [&b"product\n"[..], &[b'x'; 9000], b"\0\nduct\n"].concat();
+        let output = search_stream(&config, &late).unwrap();
+        assert_eq!("product\nBinary file - matches\n", output);
+        config.output = OutputMode::Count;
+        assert_eq!("2\n", search_stream(&config, input).unwrap());
+        config.output = OutputMode::Lines;
+        config.text = true;
+        assert_eq!("product\nduct\n", search_stream(&config, input).unwrap());
+    }
+
+    #[test]
+    fn missing_file_is_an_error() {
+        let config = Config {
+            patterns: vec![String::from("duct")],
+            file_paths: vec![String::from("no/such/file.txt")],
+            ..Config::default()
+        };
+        // reported on stderr, like grep, and the exit status says so
+        let outcome = run(config).unwrap();
+        assert!(outcome.had_errors);
+        assert_eq!(2, outcome.exit_code());
+    }
+
+    #[test]
+    fn invalid_regex_is_an_error() {
+        let config = Config {
+            patterns: vec![String::from("fn (\\w+")],
+            file_paths: vec![String::from("Cargo.toml")],
+            use_regex: true,
+            ..Config::default()
+        };
+        let err = run(config).unwrap_err();
+        assert!(err.to_string().contains("unclosed group"));
+    }
 }
@@ -1,16 +1,29 @@
-use mini_grep::{Config, run};
+use mini_grep::{Config, config, run};
 use std::{env, process};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     let config = Config::build(&args).unwrap_or_else(|err| {
         eprintln!("Problem parsing arguments: {err}");
-        process::exit(1);
+        process::exit(err.exit_code());
     });
-    println!("Searching for \"{}\" in file {}...", config.query, config.file_path);
+    if config.help {
+        print!("{}", config::usage());
+        return;
+    }
+    if config.version {
+        println!("{}", config::version());
+        return;
+    }
 
-    if let Err(e) = run(config) {
-        eprintln!("Application error: {e}");
-        process::exit(1);
+    // feat: grep-compatible exit codes, 0 on a match, 1 on none, 2 on error
+    match run(config) {
+        Ok(outcome) => process::exit(outcome.exit_code()),
+        // like ripgrep, `| head` is a normal way to stop
+        Err(e) if e.is_broken_pipe() => process::exit(0),
+        Err(e) => {
+            eprintln!("Application error: {e}");
+            process::exit(e.exit_code());
+        }
     }
 }
@@ -0,0 +1,262 @@
+// Byte and substring search over whole buffers, for the literal fast path.
+//
+// `memchr`, `memrchr` and `count` look at eight bytes at a time: XOR-ing a
+// word with the byte repeated eight times turns every occurrence into a zero
+// byte, and a few arithmetic operations find the zero bytes of a word without
+// looking at them one by one. That is the same trick SIMD versions use with
+// wider registers, written in plain Rust.
+//
+// `Finder` looks for a whole needle with the two-way algorithm of Crochemore
+// and Perrin, which never backtracks in the haystack and needs no table, so
+// it runs in linear time with constant extra space even for needles such as
+// "aaaab" that make naive search quadratic. It jumps to the next occurrence
+// of the needle's first byte with `memchr` whenever it has nothing to lose.
+const LO: u64 = 0x0101_0101_0101_0101;
+const HI: u64 = 0x8080_8080_8080_8080;
+const WORD: usize = 8;
+
+// The high bit of each byte of `x` that is zero, and no other bits.
+fn zero_bytes(x: u64) -> u64 {
+    !(((x & !HI) + !HI) | x | !HI)
+}
+
+fn word(chunk: &[u8]) -> u64 {
+    u64::from_le_bytes(chunk.try_into().unwrap())
+}
+
+// The index of the first `byte` in `haystack`.
+pub fn memchr(byte: u8, haystack: &[u8]) -> Option<usize> {
+    let splat = LO * u64::from(byte);
+    let chunks = haystack.chunks_exact(WORD);
+    let tail = chunks.remainder();
+    for (i, chunk) in chunks.enumerate() {
+        let found = zero_bytes(word(chunk) ^ splat);
+        if found != 0 {
+            // little endian: the lowest set bit is the first byte
+            return Some(i * WORD + found.trailing_zeros() as usize / 8);
+        }
+    }
+    let start = haystack.len() - tail.len();
+    tail.iter().position(|&b| b == byte).map(|i| start + i)
+}
+
+// The index of the last `byte` in `haystack`.
+pub fn memrchr(byte: u8, haystack: &[u8]) -> Option<usize> {
+    let splat = LO * u64::from(byte);
+    let chunks = haystack.rchunks_exact(WORD);
+    let head = chunks.remainder();
+    for (i, chunk) in chunks.enumerate() {
+        let found = zero_bytes(word(chunk) ^ splat);
+        if found != 0 {
+            let end = haystack.len() - i * WORD;
+            return Some(end - 1 - found.leading_zeros() as usize / 8);
+        }
+    }
+    head.iter().rposition(|&b| b == byte)
+}
+
+// How many times `byte` occurs in `haystack`.
+pub fn count(byte: u8, haystack: &[u8]) -> usize {
+    let splat = LO * u64::from(byte);
+    let chunks = haystack.chunks_exact(WORD);
+    let tail = chunks.remainder();
+    let mut n = 0;
+    for chunk in chunks {
+        n += zero_bytes(word(chunk) ^ splat).count_ones() as usize;
+    }
+    n + tail.iter().filter(|&&b| b == byte).count()
+}
+
+// A needle compiled for repeated two-way search.
+#[derive(Debug, Clone)]
+pub struct Finder {
+    needle: Vec<u8>,
+    // the needle is split into needle[..crit_pos] and needle[crit_pos..]
+    crit_pos: usize,
+    period: usize,
+    // whether the needle is periodic, so that after a mismatch in the left
+    // half the prefix that is known to match can be remembered
+    periodic: bool,
+    // bit b is set when a byte of the needle is b modulo 64
+    byteset: u64,
+}
+
+impl Finder {
+    pub fn new(needle: &[u8]) -> Finder {
+        let (pos_less, period_less) = maximal_suffix(needle, false);
+        let (pos_greater, period_greater) = maximal_suffix(needle, true);
+        let (crit_pos, period) = if pos_less > pos_greater {
+            (pos_less, period_less)
+        } else {
+            (pos_greater, period_greater)
+        };
+        let periodic = needle.get(period..period + crit_pos) == Some(&needle[..crit_pos]);
+        let period = if periodic {
+            period
+        } else {
+            crit_pos.max(needle.len() - crit_pos) + 1
+        };
+        let byteset = needle.iter().fold(0, |set, &b| set | 1 << (b % 64));
+        Finder {
+            needle: needle.to_vec(),
+            crit_pos,
+            period,
+            periodic,
+            byteset,
+        }
+    }
+
+    pub fn needle(&self) -> &[u8] {
+        &self.needle
+    }
+
+    // The index of the first occurrence of the needle in `haystack`.
+    pub fn find(&self, haystack: &[u8]) -> Option<usize> {
+        let needle = &self.needle[..];
+        let n = needle.len();
+        let Some(&first) = needle.first() else {
+            return Some(0);
+        };
+        let mut pos = 0;
+        // how much of the needle's start is known to match at `pos`
+        let mut memory = 0;
+        'search: loop {
+            if memory == 0 {
+                pos += memchr(first, haystack.get(pos..)?)?;
+            }
+            let last = *haystack.get(pos + n - 1)?;
+            if self.byteset & (1 << (last % 64)) == 0 {
+                // no match can overlap that byte
+                pos += n;
+                memory = 0;
+                continue;
+            }
+            // the right half first, then the left half
+            for i in self.crit_pos.max(memory)..n {
+                if needle[i] != haystack[pos + i] {
+                    pos += i - self.crit_pos + 1;
+                    memory = 0;
+                    continue 'search;
+                }
+            }
+            for i in (memory..self.crit_pos).rev() {
+                if needle[i] != haystack[pos + i] {
+                    pos += self.period;
+                    memory = if self.periodic { n - self.period } else { 0 };
+                    continue 'search;
+                }
+            }
+            return Some(pos);
+        }
+    }
+}
+
+// The start and period of the lexicographically largest suffix of `needle`,
+// with the byte order reversed when `greater` is false.
+fn maximal_suffix(needle: &[u8], greater: bool) -> (usize, usize) {
+    let mut left = 0;
+    let mut right = 1;
+    let mut offset = 0;
+    let mut period = 1;
+    while let Some(&a) = needle.get(right + offset) {
+        let b = needle[left + offset];
+        if (a < b && !greater) || (a > b && greater) {
+            right += offset + 1;
+            offset = 0;
+            period = right - left;
+        } else if a == b {
+            if offset + 1 == period {
+                right += offset + 1;
+                offset = 0;
+            } else {
+                offset += 1;
+            }
+        } else {
+            left = right;
+            right += 1;
+            offset = 0;
+            period = 1;
+        }
+    }
+    (left, period)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn naive(needle: &[u8], haystack: &[u8]) -> Option<usize> {
+        if needle.is_empty() {
+            return Some(0);
+        }
+        haystack.windows(needle.len()).position(|w| w == needle)
+    }
+
+    // a small deterministic generator, so failures can be reproduced
+    fn random_bytes(seed: &mut u64, len: usize, alphabet: &[u8]) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                *seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                alphabet[(*seed >> 33) as usize % alphabet.len()]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bytes_at_every_position() {
+        let haystack: Vec<u8> = (0..40).collect();
+        for i in 0..40 {
+            assert_eq!(Some(i), memchr(i as u8, &haystack));
+            assert_eq!(Some(i), memrchr(i as u8, &haystack));
+            assert_eq!(Some(i), memchr(i as u8, &haystack[..=i]));
+            assert_eq!(1, count(i as u8, &haystack));
+        }
+        assert_eq!(None, memchr(b'x', b""));
+        assert_eq!(None, memrchr(200, &haystack));
+        // bytes with the high bit set must not look like zero bytes
+        assert_eq!(
+            Some(9),
+            memchr(0, b"\x80\x80\x80\x80\x80\x80\x80\x80\x01\x00")
+        );
+    }
+
+    #[test]
+    fn first_and_last_of_many() {
+        let haystack = b"a\nbb\nccc\ndddd\neeeee\nffffff\n";
+        assert_eq!(Some(1), memchr(b'\n', haystack));
+        assert_eq!(Some(haystack.len() - 1), memrchr(b'\n', haystack));
+        assert_eq!(Some(4), memrchr(b'\n', &haystack[..8]));
+        assert_eq!(6, count(b'\n', haystack));
+        assert_eq!(6, count(b'f', haystack));
+    }
+
+    #[test]
+    fn finds_needles() {
+        let find =
+            |needle: &str, haystack: &str| Finder::new(needle.as_bytes()).find(haystack.as_bytes());
+        assert_eq!(Some(4), find("duct", "pro-duct"));
+        assert_eq!(Some(0), find("", "abc"));
+        assert_eq!(None, find("abcd", "abc"));
+        assert_eq!(Some(3), find("aab", "aaaaab"));
+        assert_eq!(Some(2), find("abab", "acabab"));
+        assert_eq!(Some(7), find("ünï", "naïve ünïcödé"));
+    }
+
+    #[test]
+    fn agrees_with_naive_search() {
+        let mut seed = 7;
+        for alphabet in [&b"ab"[..], b"abc", b"a\nb\x80"] {
+            for _ in 0..500 {
+                let len = (seed >> 40) as usize % 8 + 1;
+                let needle = random_bytes(&mut seed, len, alphabet);
+                let haystack = random_bytes(&mut seed, 64, alphabet);
+                let finder = Finder::new(&needle);
+                assert_eq!(
+                    naive(&needle, &haystack),
+                    finder.find(&haystack),
+                    "{needle:?} in {haystack:?}"
+                );
+            }
+        }
+    }
+}
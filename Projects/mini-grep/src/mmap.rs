@@ -0,0 +1,155 @@
+// The contents of a file as one slice of bytes, for the literal fast path.
+//
+// Large regular files are memory-mapped, so the kernel pages them in as the
+// search reaches them instead of copying them into a buffer first. Small
+// files are cheaper to read than to map, so those are read into memory.
+// Anything else (pipes, or large files where mapping is not supported) is
+// left to be streamed, as reading it whole could take any amount of memory.
+//
+// Like every program that maps files, mini-grep may be killed with SIGBUS if
+// another process truncates a file while it is being searched.
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Deref;
+
+// Files smaller than this are read rather than mapped.
+const MIN_LEN: u64 = 64 * 1024;
+
+pub struct Mmap {
+    inner: Inner,
+}
+
+enum Inner {
+    Read(Vec<u8>),
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    Mapped(sys::Mapping),
+}
+
+impl Mmap {
+    // `None` if the file has to be streamed instead.
+    pub fn open(mut file: &File) -> io::Result<Option<Mmap>> {
+        let metadata = file.metadata()?;
+        if !metadata.is_file() {
+            return Ok(None);
+        }
+        if metadata.len() < MIN_LEN {
+            let mut contents = Vec::with_capacity(metadata.len() as usize);
+            file.read_to_end(&mut contents)?;
+            return Ok(Some(Mmap {
+                inner: Inner::Read(contents),
+            }));
+        }
+        #[cfg(all(unix, target_pointer_width = "64"))]
+        {
+            let mapping = sys::Mapping::new(file, metadata.len() as usize)?;
+            Ok(Some(Mmap {
+                inner: Inner::Mapped(mapping),
+            }))
+        }
+        #[cfg(not(all(unix, target_pointer_width = "64")))]
+        Ok(None)
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.inner {
+            Inner::Read(contents) => contents,
+            #[cfg(all(unix, target_pointer_width = "64"))]
+            Inner::Mapped(mapping) => mapping.as_slice(),
+        }
+    }
+}
+
+// mmap(2) and munmap(2) from the C library, which Rust already links.
+#[cfg(all(unix, target_pointer_width = "64"))]
+mod sys {
+    use std::ffi::c_void;
+    use std::fs::File;
+    use std::io;
+    use std::os::fd::AsRawFd;
+
+    const PROT_READ: i32 = 1;
+    const MAP_PRIVATE: i32 = 2;
+    const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+
+    unsafe extern "C" {
+        fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: i32,
+            flags: i32,
+            fd: i32,
+            offset: i64,
+        ) -> *mut c_void;
+        fn munmap(addr: *mut c_void, len: usize) -> i32;
+    }
+
+    pub struct Mapping {
+        ptr: *mut c_void,
+        len: usize,
+    }
+
+    impl Mapping {
+        // `len` must be more than zero, which mmap requires.
+        pub fn new(file: &File, len: usize) -> io::Result<Mapping> {
+            // SAFETY: a fresh read-only private mapping of an open file
+            // aliases no Rust memory
+            let ptr = unsafe {
+                mmap(
+                    std::ptr::null_mut(),
+                    len,
+                    PROT_READ,
+                    MAP_PRIVATE,
+                    file.as_raw_fd(),
+                    0,
+                )
+            };
+            if ptr == MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Mapping { ptr, len })
+        }
+
+        pub fn as_slice(&self) -> &[u8] {
+            // SAFETY: `ptr` points to `len` readable bytes until `drop`
+            unsafe { std::slice::from_raw_parts(self.ptr.cast(), self.len) }
+        }
+    }
+
+    impl Drop for Mapping {
+        fn drop(&mut self) {
+            // SAFETY: unmaps exactly what `new` mapped, once
+            unsafe {
+                munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempdir::TempDir;
+    use std::fs;
+
+    #[test]
+    fn small_and_large_files() {
+        let dir = TempDir::new("mmap");
+        let small = dir.join("small.txt");
+        let large = dir.join("large.txt");
+        fs::write(&small, "hello\n").unwrap();
+        let contents = "all work and no play\n".repeat(10_000);
+        fs::write(&large, &contents).unwrap();
+
+        let read = Mmap::open(&File::open(&small).unwrap()).unwrap().unwrap();
+        let mapped = Mmap::open(&File::open(&large).unwrap()).unwrap().unwrap();
+
+        assert_eq!(b"hello\n", &read[..]);
+        assert_eq!(contents.as_bytes(), &mapped[..]);
+        // directories, pipes and the like are streamed
+        assert!(Mmap::open(&File::open(".").unwrap()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,380 @@
+// Searches many files at once on a pool of worker threads.
+//
+// One thread walks the paths from the config and hands out numbered jobs over
+// a bounded channel, the workers search each file and send what they print
+// back in pieces of bounded size, and the calling thread prints those
+// strictly in job order. The output is therefore byte for byte what a
+// single-threaded search would print. The job at the head of the order is
+// printed as it goes, so standard input streams through line by line; only
+// jobs that get ahead of it are held back in memory.
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+
+use crate::{
+    Config, GrepError, Outcome, OutputMode, Pattern, Printer, Searcher, Stats, Walk, gzip, replace,
+};
+
+enum Job {
+    Stdin,
+    // `walked` files were found inside a directory; problems with them are
+    // reported and skipped instead of ending the search
+    File { path: PathBuf, walked: bool },
+    // a directory entry that could not be read
+    WalkError(io::Error),
+}
+
+// Output is sent on once a job has printed this much.
+const CHUNK: usize = 64 * 1024;
+
+// What the workers send back while searching.
+enum Message {
+    // more of the output of the job with this index
+    Output(usize, Vec<u8>),
+    // the job is finished; its output has all been sent
+    Done(Done),
+}
+
+// What searching one job found, besides its output.
+struct Done {
+    index: usize,
+    selected: usize,
+    stats: Stats,
+    // a file that could not be searched; reported, and the run goes on
+    warning: Option<String>,
+}
+
+// The number of workers used when `-j` is not given.
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+pub(crate) fn search_all<W: Write>(
+    config: &Config,
+    pattern: &Pattern,
+    printer: &mut Printer<W>,
+    color: bool,
+) -> Result<Outcome, GrepError> {
+    let threads = match config.threads {
+        0 => default_threads(),
+        n => n,
+    };
+    // set once the result is known, so the other threads wind down early
+    let stop = AtomicBool::new(false);
+    let (job_tx, job_rx) = mpsc::sync_channel(threads * 2);
+    let job_rx = Mutex::new(job_rx);
+    // bounded, so a slow reader of the output holds the workers back
+    let (done_tx, done_rx) = mpsc::sync_channel(threads * 4);
+
+    thread::scope(|scope| {
+        let stop = &stop;
+        scope.spawn(move || produce(config, stop, job_tx));
+        for _ in 0..threads {
+            let job_rx = &job_rx;
+            let done_tx = done_tx.clone();
+            scope.spawn(move || {
+                loop {
+                    let Ok((index, job)) = job_rx.lock().unwrap().recv() else {
+                        break;
+                    };
+                    // keep draining after a stop so the producer never blocks
+                    if stop.load(Ordering::Relaxed) {
+                        continue;
+                    }
+                    let done = search_job(config, pattern, color, index, job, &done_tx);
+                    if done_tx.send(Message::Done(done)).is_err() {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                }
+            });
+        }
+        drop(done_tx);
+
+        let result = collect(config, printer, done_rx);
+        stop.store(true, Ordering::Relaxed);
+        result
+    })
+}
+
+fn produce(config: &Config, stop: &AtomicBool, jobs: SyncSender<(usize, Job)>) {
+    let mut index = 0;
+    let mut send = |job| {
+        if stop.load(Ordering::Relaxed) {
+            return false;
+        }
+        index += 1;
+        jobs.send((index - 1, job)).is_ok()
+    };
+    for file_path in &config.file_paths {
+        // feat: `-` reads from stdin, so output can be piped into mini-grep
+        if file_path == "-" {
+            if !send(Job::Stdin) {
+                return;
+            }
+        } else if Path::new(file_path).is_dir() {
+            // feat: search every file below a directory, prefixing results with paths
+            let walk = Walk::new(file_path)
+                .hidden(config.hidden)
+                .include(&config.include)
+                .exclude(&config.exclude);
+            for entry in walk {
+                let job = match entry {
+                    Ok(path) => Job::File { path, walked: true },
+                    Err(e) => Job::WalkError(e),
+                };
+                if !send(job) {
+                    return;
+                }
+            }
+        } else {
+            let path = PathBuf::from(file_path);
+            if !send(Job::File {
+                path,
+                walked: false,
+            }) {
+                return;
+            }
+        }
+    }
+}
+
+fn search_job(
+    config: &Config,
+    pattern: &Pattern,
+    color: bool,
+    index: usize,
+    job: Job,
+    tx: &SyncSender<Message>,
+) -> Done {
+    let mut done = Done {
+        index,
+        selected: 0,
+        stats: Stats::default(),
+        warning: None,
+    };
+    let stream = Stream {
+        index,
+        buf: Vec::new(),
+        tx: tx.clone(),
+        // a pipe may take its time, so pass on every line at once
+        line_buffered: matches!(job, Job::Stdin),
+    };
+    let mut printer = Printer::new(config, stream).color(color);
+    match job {
+        Job::Stdin => {
+            let path = Path::new("(standard input)");
+            printer.begin(path);
+            let searched =
+                gzip::maybe_decompress(config.decompress, io::stdin().lock()).and_then(|reader| {
+                    Searcher::from(config).search_reader(pattern, reader, &mut printer)
+                });
+            match searched {
+                Ok(selected) => done.selected = selected,
+                Err(e) => done.warning = Some(GrepError::io(path, e).to_string()),
+            }
+        }
+        Job::File { path, walked } => {
+            let searched = File::open(&path).and_then(|file| {
+                printer.begin(&path);
+                search_file(config, pattern, &mut printer, &path, file)
+            });
+            match searched {
+                Ok(selected) => done.selected = selected,
+                // --write skips files in directories that are not UTF-8
+                Err(e) if walked && config.write && e.kind() == io::ErrorKind::InvalidData => {}
+                Err(e) => done.warning = Some(GrepError::io(path, e).to_string()),
+            }
+        }
+        Job::WalkError(e) => done.warning = Some(e.to_string()),
+    }
+    done.stats = printer.stats();
+    // if nobody is listening any more, the run is stopping anyway
+    let _ = printer.into_inner().flush();
+    done
+}
+
+// Where the printer of a job writes: collects the output and sends it on
+// in pieces.
+struct Stream {
+    index: usize,
+    buf: Vec<u8>,
+    tx: SyncSender<Message>,
+    line_buffered: bool,
+}
+
+impl Write for Stream {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= CHUNK || (self.line_buffered && self.buf.ends_with(b"\n")) {
+            self.flush()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let output = mem::take(&mut self.buf);
+        self.tx
+            .send(Message::Output(self.index, output))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+}
+
+// The output of a job that is not at the head of the order yet.
+#[derive(Default)]
+struct Held {
+    chunks: Vec<Vec<u8>>,
+    done: Option<Done>,
+}
+
+// Searches an opened file, or with --write replaces the matches in it.
+fn search_file<W: Write>(
+    config: &Config,
+    pattern: &Pattern,
+    printer: &mut Printer<W>,
+    path: &Path,
+    file: File,
+) -> io::Result<usize> {
+    if config.write {
+        return replace::rewrite(config, pattern, printer, path, BufReader::new(file));
+    }
+    let searcher = Searcher::from(config);
+    // feat: -z searches gzip-compressed files as if they were plain text
+    if config.decompress {
+        let reader = gzip::maybe_decompress(true, BufReader::new(file))?;
+        return searcher.search_reader(pattern, reader, printer);
+    }
+    // feat: a literal is looked for in the whole file at once
+    searcher.search_file(pattern, &file, printer)
+}
+
+// Prints the output of the jobs in order: that of the job at the head as it
+// arrives, and that of later jobs once all before them are done.
+fn collect<W: Write>(
+    config: &Config,
+    printer: &mut Printer<W>,
+    done_rx: Receiver<Message>,
+) -> Result<Outcome, GrepError> {
+    let quiet = config.output == OutputMode::Quiet;
+    let mut held: BTreeMap<usize, Held> = BTreeMap::new();
+    let mut next = 0;
+    // whether the job at the head has printed anything yet
+    let mut started = false;
+    let mut outcome = Outcome::default();
+    let mut stats = Stats::default();
+    let print = |printer: &mut Printer<W>, started: &mut bool, output: &[u8]| {
+        let printed = if mem::replace(started, true) {
+            printer.append_more(output)
+        } else {
+            printer.append(output)
+        };
+        printed.map_err(|e| GrepError::io("(standard output)", e))
+    };
+
+    for message in done_rx {
+        match message {
+            Message::Output(index, output) if index == next => {
+                print(printer, &mut started, &output)?;
+            }
+            Message::Output(index, output) => held.entry(index).or_default().chunks.push(output),
+            Message::Done(done) => {
+                let index = done.index;
+                held.entry(index).or_default().done = Some(done);
+            }
+        }
+        while let Some(head) = held.get_mut(&next) {
+            for chunk in mem::take(&mut head.chunks) {
+                print(printer, &mut started, &chunk)?;
+            }
+            let Some(done) = head.done.take() else {
+                break;
+            };
+            held.remove(&next);
+            next += 1;
+            started = false;
+            if let Some(warning) = done.warning {
+                eprintln!("mini-grep: {warning}");
+                outcome.had_errors = true;
+            }
+            outcome.matched |= done.selected > 0;
+            stats += done.stats;
+            // feat: -q only cares whether something matched, so stop right away
+            if quiet && outcome.matched {
+                return Ok(outcome);
+            }
+        }
+    }
+    printer
+        .summary(stats)
+        .map_err(|e| GrepError::io("(standard output)", e))?;
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempdir::TempDir;
+    use std::fs;
+
+    fn search(config: &Config) -> String {
+        let pattern = Pattern::new(config).unwrap();
+        let mut printer = Printer::new(config, Vec::new());
+        search_all(config, &pattern, &mut printer, false).unwrap();
+        String::from_utf8(printer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn unreadable_files_do_not_stop_the_run() {
+        let root = TempDir::new("errors");
+        fs::write(root.join("b.txt"), "needle\n").unwrap();
+        let config = Config {
+            patterns: vec![String::from("needle")],
+            file_paths: vec![
+                root.join("a.txt").display().to_string(),
+                root.join("b.txt").display().to_string(),
+            ],
+            ..Config::default()
+        };
+        let pattern = Pattern::new(&config).unwrap();
+        let mut printer = Printer::new(&config, Vec::new());
+        let outcome = search_all(&config, &pattern, &mut printer, false).unwrap();
+
+        let output = String::from_utf8(printer.into_inner()).unwrap();
+        assert_eq!(format!("{}:needle\n", root.join("b.txt").display()), output);
+        assert!(outcome.matched && outcome.had_errors);
+        assert_eq!(2, outcome.exit_code());
+    }
+
+    #[test]
+    fn output_order_does_not_depend_on_threads() {
+        let root = TempDir::new("parallel");
+        for i in 0..40 {
+            let body = "needle\nhay\n".repeat(i % 7 + 1);
+            fs::write(root.join(format!("{i:02}.txt")), body).unwrap();
+        }
+        let mut config = Config {
+            patterns: vec![String::from("needle")],
+            file_paths: vec![root.display().to_string()],
+            line_number: true,
+            after_context: 1,
+            threads: 1,
+            ..Config::default()
+        };
+        let sequential = search(&config);
+        config.threads = 8;
+        let parallel = search(&config);
+
+        assert_eq!(sequential, parallel);
+        assert!(parallel.starts_with(&format!("{}:1:needle\n", root.join("00.txt").display())));
+        // one group per file, so one separator between each pair of files
+        assert_eq!(39, parallel.matches("--\n").count());
+    }
+}
@@ -0,0 +1,613 @@
+// Formats search results. Lines are fed in file order, one at a time, so the
+// printer only needs to remember the last `before_context` lines of a file.
+//
+// Results are printed as text like grep's, or with `--json` as JSON Lines:
+// one object per line, with a "type" of "begin" and "end" around the
+// "match" and "context" records of each file that has any, and a final
+// "summary" of the whole run.
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::ops::{AddAssign, Range};
+use std::path::Path;
+
+use crate::json::JsonStr;
+use crate::replace::Change;
+use crate::{Config, Match, OutputMode, Sink, replace_all};
+
+// SGR sequences for the parts of a line, using grep's default colors.
+const MATCH_COLOR: &str = "\x1b[1;31m";
+const PATH_COLOR: &str = "\x1b[35m";
+const LINE_NUMBER_COLOR: &str = "\x1b[32m";
+const SEPARATOR_COLOR: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+// Totals over the files a printer was given.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub searches: usize,
+    pub searches_with_match: usize,
+    pub matched_lines: usize,
+    // every match on every line, not just the first of each line
+    pub matches: usize,
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Stats) {
+        self.searches += other.searches;
+        self.searches_with_match += other.searches_with_match;
+        self.matched_lines += other.matched_lines;
+        self.matches += other.matches;
+    }
+}
+
+pub struct Printer<W: Write> {
+    out: W,
+    color: bool,
+    json: bool,
+    output: OutputMode,
+    with_filename: bool,
+    line_number: bool,
+    column: bool,
+    // with --replace, what each match is printed as
+    replacement: Option<String>,
+    before_context: usize,
+    after_context: usize,
+    // whether groups of lines are divided by `--`
+    separate_groups: bool,
+    // state for the file currently being printed
+    path: String,
+    before: VecDeque<(usize, String)>,
+    after_left: usize,
+    last_printed: Option<usize>,
+    // whether any line has been printed yet, across all files
+    printed_any: bool,
+    // whether the JSON "begin" record of the current file is out
+    begun: bool,
+    file_matches: usize,
+    stats: Stats,
+}
+
+impl<W: Write> Printer<W> {
+    pub fn new(config: &Config, out: W) -> Printer<W> {
+        // like grep, name the file on each line once more than one can match
+        let with_filename = config.with_filename
+            || config.file_paths.len() > 1
+            || config.file_paths.iter().any(|p| Path::new(p).is_dir());
+        Printer {
+            out,
+            color: false,
+            json: config.json,
+            output: config.output,
+            with_filename,
+            line_number: config.line_number || config.column,
+            column: config.column,
+            replacement: config.replace.clone(),
+            before_context: config.before_context,
+            after_context: config.after_context,
+            separate_groups: (config.before_context > 0 || config.after_context > 0)
+                && config.output == OutputMode::Lines
+                && !config.json,
+            path: String::new(),
+            before: VecDeque::new(),
+            after_left: 0,
+            last_printed: None,
+            printed_any: false,
+            begun: false,
+            file_matches: 0,
+            stats: Stats::default(),
+        }
+    }
+
+    // Turns ANSI highlighting of matches and prefixes on or off.
+    pub fn color(mut self, yes: bool) -> Printer<W> {
+        self.color = yes;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    // Copies the complete output another printer produced for one file, as
+    // if this printer had printed it.
+    pub fn append(&mut self, output: &[u8]) -> io::Result<()> {
+        if output.is_empty() {
+            return Ok(());
+        }
+        if self.printed_any {
+            self.group_separator()?;
+        }
+        self.printed_any = true;
+        self.out.write_all(output)
+    }
+
+    // Copies more of the output whose start was passed to `append`.
+    pub fn append_more(&mut self, output: &[u8]) -> io::Result<()> {
+        self.out.write_all(output)
+    }
+
+    fn group_separator(&mut self) -> io::Result<()> {
+        if self.separate_groups {
+            paint(&mut self.out, self.color, SEPARATOR_COLOR, "--")?;
+            writeln!(self.out)?;
+        }
+        Ok(())
+    }
+
+    // Starts a new file.
+    pub fn begin(&mut self, path: &Path) {
+        self.path = path.display().to_string();
+        self.before.clear();
+        self.after_left = 0;
+        self.last_printed = None;
+        self.begun = false;
+        self.file_matches = 0;
+        self.stats.searches += 1;
+    }
+
+    // Finishes the current file, in which `selected` lines were selected.
+    pub fn end(&mut self, selected: usize) -> io::Result<()> {
+        self.stats.matched_lines += selected;
+        if selected > 0 {
+            self.stats.searches_with_match += 1;
+        }
+        if !self.begun {
+            return Ok(());
+        }
+        writeln!(
+            self.out,
+            r#"{{"type":"end","path":{},"matched_lines":{selected},"matches":{}}}"#,
+            JsonStr(&self.path),
+            self.file_matches
+        )
+    }
+
+    // The totals of a whole run; only printed as JSON, and not with -q,
+    // which only sets the exit status.
+    pub fn summary(&mut self, stats: Stats) -> io::Result<()> {
+        if !self.json || self.output == OutputMode::Quiet {
+            return Ok(());
+        }
+        writeln!(
+            self.out,
+            r#"{{"type":"summary","searches":{},"searches_with_match":{},"matched_lines":{},"matches":{}}}"#,
+            stats.searches, stats.searches_with_match, stats.matched_lines, stats.matches
+        )
+    }
+
+    pub fn matched(&mut self, m: &Match) -> io::Result<()> {
+        self.matched_all(m, std::slice::from_ref(&m.span))
+    }
+
+    // A selected line with all the matches in it, in order; lines selected
+    // by -v have none. A multiline match covers several lines, each printed
+    // with its own prefix, and a hit may continue from one to the next.
+    pub fn matched_all(&mut self, m: &Match, spans: &[Range<usize>]) -> io::Result<()> {
+        while let Some((line_number, line)) = self.before.pop_front() {
+            self.write_line(line_number, &line, '-')?;
+        }
+        self.after_left = self.after_context;
+        // empty matches count too: --replace inserts at them
+        let spans: Vec<_> = spans.iter().collect();
+        self.file_matches += spans.len();
+        self.stats.matches += spans.len();
+        if self.json {
+            return self.write_json_match(m, &spans);
+        }
+        let mut start = 0;
+        for (i, line) in m.line.split('\n').enumerate() {
+            let end = start + line.len();
+            let column = (i == 0).then(|| m.column());
+            self.write_prefix(m.line_number + i, column, m.distance, ':')?;
+            let mut at = start;
+            // the part of each hit that is on this line; an empty one is on
+            // the line it is at
+            for span in spans
+                .iter()
+                .filter(|span| span.start <= end && (span.end > start || span.start == start))
+            {
+                let (from, to) = (span.start.max(start), span.end.min(end));
+                write!(self.out, "{}", &m.line[at..from])?;
+                let hit = match &self.replacement {
+                    // replaced where the hit starts
+                    Some(_) if span.start < start => None,
+                    Some(replacement) => Some(replacement.as_str()),
+                    None => Some(&m.line[from..to]),
+                };
+                if let Some(hit) = hit.filter(|hit| !hit.is_empty()) {
+                    paint(&mut self.out, self.color, MATCH_COLOR, hit)?;
+                }
+                at = to;
+            }
+            writeln!(self.out, "{}", &m.line[at..end])?;
+            start = end + 1;
+        }
+        Ok(())
+    }
+
+    // A line that was not selected; printed only if it is within context.
+    pub fn context(&mut self, line_number: usize, line: &str) -> io::Result<()> {
+        if self.after_left > 0 {
+            self.after_left -= 1;
+            return self.write_line(line_number, line, '-');
+        }
+        if self.before_context > 0 {
+            if self.before.len() == self.before_context {
+                self.before.pop_front();
+            }
+            self.before.push_back((line_number, line.to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn count(&mut self, count: usize) -> io::Result<()> {
+        if self.with_filename {
+            self.write_path(':')?;
+        }
+        writeln!(self.out, "{count}")
+    }
+
+    // Stands in for the lines of a binary file; JSON output has no record
+    // for it, so there the file only shows up in the summary.
+    pub fn binary_matched(&mut self) -> io::Result<()> {
+        if self.json {
+            return Ok(());
+        }
+        writeln!(self.out, "Binary file {} matches", self.path)
+    }
+
+    // The path on its own, for `-l` and `-L`.
+    pub fn file_name(&mut self) -> io::Result<()> {
+        paint(&mut self.out, self.color, PATH_COLOR, &self.path)?;
+        writeln!(self.out)
+    }
+
+    fn write_line(&mut self, line_number: usize, line: &str, sep: char) -> io::Result<()> {
+        if self.json {
+            self.write_json_begin()?;
+            return writeln!(
+                self.out,
+                r#"{{"type":"context","path":{},"line_number":{line_number},"line":{}}}"#,
+                JsonStr(&self.path),
+                JsonStr(line)
+            );
+        }
+        self.write_prefix(line_number, None, None, sep)?;
+        writeln!(self.out, "{line}")
+    }
+
+    // `sep` is ':' for matching lines and '-' for context lines, as in grep.
+    fn write_prefix(
+        &mut self,
+        line_number: usize,
+        column: Option<usize>,
+        distance: Option<usize>,
+        sep: char,
+    ) -> io::Result<()> {
+        // separate groups of lines that are not adjacent with `--`
+        let adjacent = self
+            .last_printed
+            .is_some_and(|last| last + 1 == line_number);
+        if self.printed_any && !adjacent {
+            self.group_separator()?;
+        }
+        self.last_printed = Some(line_number);
+        self.printed_any = true;
+
+        if self.with_filename {
+            self.write_path(sep)?;
+        }
+        if self.line_number {
+            paint(&mut self.out, self.color, LINE_NUMBER_COLOR, line_number)?;
+            paint(&mut self.out, self.color, SEPARATOR_COLOR, sep)?;
+        }
+        // context lines have no match, so they carry no column
+        if let (true, Some(column)) = (self.column, column) {
+            paint(&mut self.out, self.color, LINE_NUMBER_COLOR, column)?;
+            paint(&mut self.out, self.color, SEPARATOR_COLOR, sep)?;
+        }
+        // after the location, which editors read up to, and before the text
+        if let Some(distance) = distance {
+            paint(&mut self.out, self.color, LINE_NUMBER_COLOR, distance)?;
+            paint(&mut self.out, self.color, SEPARATOR_COLOR, sep)?;
+        }
+        Ok(())
+    }
+
+    fn write_json_begin(&mut self) -> io::Result<()> {
+        if self.begun {
+            return Ok(());
+        }
+        self.begun = true;
+        writeln!(
+            self.out,
+            r#"{{"type":"begin","path":{}}}"#,
+            JsonStr(&self.path)
+        )
+    }
+
+    fn write_json_match(&mut self, m: &Match, spans: &[&Range<usize>]) -> io::Result<()> {
+        self.write_json_begin()?;
+        write!(
+            self.out,
+            r#"{{"type":"match","path":{},"line_number":{},"column":{},"absolute_offset":{},"line":{},"#,
+            JsonStr(&self.path),
+            m.line_number,
+            m.column(),
+            m.byte_offset,
+            JsonStr(m.line)
+        )?;
+        if m.line.contains('\n') {
+            write!(self.out, r#""end_line_number":{},"#, m.end_line_number())?;
+        }
+        if let Some(distance) = m.distance {
+            write!(self.out, r#""distance":{distance},"#)?;
+        }
+        if let Some(replacement) = &self.replacement {
+            let spans: Vec<_> = spans.iter().map(|&span| span.clone()).collect();
+            let replaced = replace_all(m.line, &spans, replacement);
+            write!(self.out, r#""replacement":{},"#, JsonStr(&replaced))?;
+        }
+        write!(self.out, r#""submatches":["#)?;
+        for (i, span) in spans.iter().enumerate() {
+            if i > 0 {
+                write!(self.out, ",")?;
+            }
+            write!(
+                self.out,
+                r#"{{"match":{},"start":{},"end":{}}}"#,
+                JsonStr(&m.line[(*span).clone()]),
+                span.start,
+                span.end
+            )?;
+        }
+        writeln!(self.out, "]}}")
+    }
+
+    // Shows what --write would change in the current file, as a unified
+    // diff without context lines that `patch` can apply.
+    pub fn diff(&mut self, changes: &[Change]) -> io::Result<()> {
+        paint(
+            &mut self.out,
+            self.color,
+            PATH_COLOR,
+            format!("--- a/{}", self.path),
+        )?;
+        writeln!(self.out)?;
+        paint(
+            &mut self.out,
+            self.color,
+            PATH_COLOR,
+            format!("+++ b/{}", self.path),
+        )?;
+        writeln!(self.out)?;
+        // changed lines that follow each other share a hunk
+        for hunk in changes.chunk_by(|a, b| a.line_number + 1 == b.line_number) {
+            let header = match hunk.len() {
+                1 => format!("@@ -{0} +{0} @@", hunk[0].line_number),
+                n => format!("@@ -{0},{n} +{0},{n} @@", hunk[0].line_number),
+            };
+            paint(&mut self.out, self.color, SEPARATOR_COLOR, header)?;
+            writeln!(self.out)?;
+            for change in hunk {
+                writeln!(self.out, "-{}", change.old)?;
+            }
+            for change in hunk {
+                writeln!(self.out, "+{}", change.new)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_path(&mut self, sep: char) -> io::Result<()> {
+        paint(&mut self.out, self.color, PATH_COLOR, &self.path)?;
+        paint(&mut self.out, self.color, SEPARATOR_COLOR, sep)
+    }
+}
+
+// The printer is what the command line hands its searches to.
+impl<W: Write> Sink for Printer<W> {
+    fn matched(&mut self, m: &Match, spans: &[Range<usize>]) -> io::Result<bool> {
+        if self.output != OutputMode::Lines {
+            return Ok(!self.output.stops_at_first_match());
+        }
+        self.matched_all(m, spans)?;
+        Ok(true)
+    }
+
+    fn context(&mut self, line_number: usize, line: &str) -> io::Result<bool> {
+        Printer::context(self, line_number, line)?;
+        Ok(true)
+    }
+
+    // Only the lines themselves are held back; -c, -l and the like count
+    // binary files like any other.
+    fn binary(&mut self, m: &Match, spans: &[Range<usize>]) -> io::Result<bool> {
+        if self.output != OutputMode::Lines {
+            return Sink::matched(self, m, spans);
+        }
+        self.binary_matched()?;
+        Ok(false)
+    }
+
+    fn finish(&mut self, count: usize) -> io::Result<()> {
+        match self.output {
+            OutputMode::Count => self.count(count)?,
+            OutputMode::FilesWithMatches if count > 0 => self.file_name()?,
+            OutputMode::FilesWithoutMatch if count == 0 => self.file_name()?,
+            _ => {}
+        }
+        self.end(count)
+    }
+}
+
+// Writes `text`, wrapped in `color` when highlighting is `enabled`.
+fn paint(out: &mut impl Write, enabled: bool, color: &str, text: impl Display) -> io::Result<()> {
+    if enabled {
+        write!(out, "{color}{text}{RESET}")
+    } else {
+        write!(out, "{text}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // feeds every line of `contents`, treating lines containing "x" as matches
+    fn print(config: &Config, contents: &str) -> String {
+        let mut printer = Printer::new(config, Vec::new());
+        printer.begin(Path::new("-"));
+        for (index, line) in contents.lines().enumerate() {
+            match line.find('x') {
+                Some(start) => {
+                    let m = Match {
+                        line_number: index + 1,
+                        byte_offset: 0,
+                        distance: None,
+                        span: start..start + 1,
+                        line,
+                    };
+                    printer.matched(&m).unwrap();
+                }
+                None => printer.context(index + 1, line).unwrap(),
+            }
+        }
+        String::from_utf8(printer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn context_groups_are_separated() {
+        let config = Config {
+            line_number: true,
+            before_context: 1,
+            after_context: 1,
+            ..Config::default()
+        };
+        let output = print(&config, "a\nx1\nb\nc\nd\ne\nx2\nf");
+        assert_eq!("1-a\n2:x1\n3-b\n--\n6-e\n7:x2\n8-f\n", output);
+    }
+
+    #[test]
+    fn overlapping_windows_are_merged() {
+        let config = Config {
+            before_context: 2,
+            after_context: 2,
+            ..Config::default()
+        };
+        let output = print(&config, "a\nx1\nb\nc\nx2\nd\ne\nf");
+        assert_eq!("a\nx1\nb\nc\nx2\nd\ne\n", output);
+    }
+
+    #[test]
+    fn color_highlights_match_and_prefixes() {
+        let config = Config {
+            line_number: true,
+            ..Config::default()
+        };
+        let mut printer = Printer::new(&config, Vec::new()).color(true);
+        printer.begin(Path::new("-"));
+        let m = Match {
+            line_number: 3,
+            byte_offset: 0,
+            distance: None,
+            span: 4..8,
+            line: "pro duct ive",
+        };
+        printer.matched(&m).unwrap();
+        let output = String::from_utf8(printer.into_inner()).unwrap();
+        assert_eq!(
+            "\x1b[32m3\x1b[0m\x1b[36m:\x1b[0mpro \x1b[1;31mduct\x1b[0m ive\n",
+            output
+        );
+    }
+
+    #[test]
+    fn json_records() {
+        let config = Config {
+            json: true,
+            after_context: 1,
+            ..Config::default()
+        };
+        let mut printer = Printer::new(&config, Vec::new());
+        printer.begin(Path::new("src/\"q\".rs"));
+        let m = Match {
+            line_number: 2,
+            byte_offset: 6,
+            distance: None,
+            span: 0..2,
+            line: "ab\tab",
+        };
+        printer.matched_all(&m, &[0..2, 3..5]).unwrap();
+        printer.context(3, "next").unwrap();
+        printer.end(1).unwrap();
+        printer.begin(Path::new("empty.rs"));
+        printer.end(0).unwrap();
+        let stats = printer.stats();
+        printer.summary(stats).unwrap();
+        let output = String::from_utf8(printer.into_inner()).unwrap();
+        let expected = [
+            r#"{"type":"begin","path":"src/\"q\".rs"}"#,
+            r#"{"type":"match","path":"src/\"q\".rs","line_number":2,"column":1,"absolute_offset":6,"line":"ab\tab","submatches":[{"match":"ab","start":0,"end":2},{"match":"ab","start":3,"end":5}]}"#,
+            r#"{"type":"context","path":"src/\"q\".rs","line_number":3,"line":"next"}"#,
+            r#"{"type":"end","path":"src/\"q\".rs","matched_lines":1,"matches":2}"#,
+            r#"{"type":"summary","searches":2,"searches_with_match":1,"matched_lines":1,"matches":2}"#,
+        ];
+        assert_eq!(expected.join("\n") + "\n", output);
+
+        let quiet = Config {
+            json: true,
+            output: OutputMode::Quiet,
+            ..Config::default()
+        };
+        let mut printer = Printer::new(&quiet, Vec::new());
+        printer.begin(Path::new("empty.rs"));
+        printer.end(0).unwrap();
+        printer.summary(printer.stats()).unwrap();
+        assert!(printer.into_inner().is_empty());
+    }
+
+    #[test]
+    fn replacement_and_diff() {
+        let config = Config {
+            replace: Some(String::from("[$]")),
+            ..Config::default()
+        };
+        let mut printer = Printer::new(&config, Vec::new());
+        printer.begin(Path::new("a.txt"));
+        let m = Match {
+            line_number: 1,
+            byte_offset: 0,
+            distance: None,
+            span: 0..1,
+            line: "x1 x2",
+        };
+        printer.matched_all(&m, &[0..1, 3..4]).unwrap();
+        let change = |line_number, old, new: &str| Change {
+            line_number,
+            old,
+            new: new.to_string(),
+        };
+        let changes = [
+            change(1, "x1", "y1"),
+            change(2, "x2", "y2"),
+            change(5, "x", "y"),
+        ];
+        printer.diff(&changes).unwrap();
+        let output = String::from_utf8(printer.into_inner()).unwrap();
+        let expected = "[$]1 [$]2\n--- a/a.txt\n+++ b/a.txt\n\
+            @@ -1,2 +1,2 @@\n-x1\n-x2\n+y1\n+y2\n@@ -5 +5 @@\n-x\n+y\n";
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn no_separator_without_context() {
+        let config = Config::default();
+        assert_eq!("x1\nx2\n", print(&config, "x1\na\nx2"));
+    }
+}
@@ -0,0 +1,986 @@
+// A small regular expression engine for the regex search mode.
+//
+// Patterns are parsed into an AST, compiled into a tiny instruction set and
+// executed with a Pike VM, so matching time stays linear in the input and the
+// result is the leftmost-first match (the same semantics Perl and the `regex`
+// crate use). Supported syntax:
+//
+//   literals, `.`, `[...]`/`[^...]` classes with ranges, `\d \w \s` (and their
+//   negations), `^`, `$`, `\b`, `\B`, groups `(...)`/`(?:...)`, alternation `|`
+//   and the quantifiers `* + ? {n} {n,} {n,m}` (append `?` for a lazy match).
+//
+// `.` never matches a newline. With `multi_line`, `^` and `$` also match
+// right after and right before one, for searching many lines at once. With
+// `whole_words`, a match can neither follow nor be followed by a word
+// character; unlike wrapping the pattern in `\b`, that also holds for
+// matches that start or end with punctuation.
+use std::error::Error as StdError;
+use std::fmt;
+use std::ops::Range;
+
+// Upper bounds that keep a hostile pattern from blowing up the program size.
+const MAX_REPEAT: u32 = 1000;
+const MAX_INSTS: usize = 100_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    msg: String,
+    pos: usize,
+}
+
+impl Error {
+    fn new(msg: impl Into<String>, pos: usize) -> Error {
+        Error {
+            msg: msg.into(),
+            pos,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid regex at position {}: {}", self.pos, self.msg)
+    }
+}
+
+impl StdError for Error {}
+
+pub struct RegexBuilder {
+    pattern: String,
+    case_insensitive: bool,
+    multi_line: bool,
+    whole_words: bool,
+}
+
+impl RegexBuilder {
+    pub fn new(pattern: &str) -> RegexBuilder {
+        RegexBuilder {
+            pattern: pattern.to_string(),
+            case_insensitive: false,
+            multi_line: false,
+            whole_words: false,
+        }
+    }
+
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.case_insensitive = yes;
+        self
+    }
+
+    pub fn multi_line(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.multi_line = yes;
+        self
+    }
+
+    pub fn whole_words(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.whole_words = yes;
+        self
+    }
+
+    pub fn build(&self) -> Result<Regex, Error> {
+        let mut parser = Parser {
+            chars: self.pattern.chars().collect(),
+            pos: 0,
+            groups: 1,
+        };
+        let ast = parser.parse()?;
+        let mut compiler = Compiler {
+            insts: Vec::new(),
+            case_insensitive: self.case_insensitive,
+            multi_line: self.multi_line,
+        };
+        compiler.push(Inst::Save(0))?;
+        // part of the program, so every alternative gets its chance
+        if self.whole_words {
+            compiler.push(Inst::Assert(Look::NoWordBefore))?;
+        }
+        compiler.compile(&ast)?;
+        if self.whole_words {
+            compiler.push(Inst::Assert(Look::NoWordAfter))?;
+        }
+        compiler.push(Inst::Save(1))?;
+        compiler.push(Inst::Match)?;
+        Ok(Regex {
+            pattern: self.pattern.clone(),
+            insts: compiler.insts,
+            slots: parser.groups * 2,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    pattern: String,
+    insts: Vec<Inst>,
+    slots: usize,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, Error> {
+        RegexBuilder::new(pattern).build()
+    }
+
+    pub fn builder(pattern: &str) -> RegexBuilder {
+        RegexBuilder::new(pattern)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    pub fn find(&self, text: &str) -> Option<Range<usize>> {
+        self.find_at(text.as_bytes(), 0)
+    }
+
+    // Finds the leftmost-first match starting the scan at byte `start`. The
+    // haystack does not have to be valid UTF-8: invalid bytes are treated as
+    // U+FFFD one byte at a time.
+    pub fn find_at(&self, haystack: &[u8], start: usize) -> Option<Range<usize>> {
+        let slots = self.exec(haystack, start)?;
+        Some(slots[0]?..slots[1]?)
+    }
+
+    fn exec(&self, haystack: &[u8], start: usize) -> Option<Vec<Option<usize>>> {
+        let mut clist = Threads::new(self.insts.len());
+        let mut nlist = Threads::new(self.insts.len());
+        let mut matched: Option<Vec<Option<usize>>> = None;
+        let mut pos = start;
+        loop {
+            if matched.is_none() {
+                let slots = vec![None; self.slots];
+                self.add_thread(&mut clist, 0, pos, haystack, slots);
+            }
+            if clist.is_empty() && matched.is_some() {
+                break;
+            }
+            let next = decode(haystack, pos);
+            for i in 0..clist.threads.len() {
+                let (pc, ref slots) = clist.threads[i];
+                let step = match (&self.insts[pc], next) {
+                    (Inst::Match, _) => {
+                        matched = Some(slots.clone());
+                        // Lower-priority threads can never win over this one.
+                        break;
+                    }
+                    (Inst::Char(want), Some((c, _))) => *want == c,
+                    (Inst::Any, Some((c, _))) => c != '\n',
+                    (Inst::Class(class), Some((c, _))) => class.matches(c),
+                    _ => false,
+                };
+                if step {
+                    let len = next.map_or(0, |(_, len)| len);
+                    let slots = slots.clone();
+                    self.add_thread(&mut nlist, pc + 1, pos + len, haystack, slots);
+                }
+            }
+            match next {
+                Some((_, len)) => pos += len,
+                None => break,
+            }
+            std::mem::swap(&mut clist, &mut nlist);
+            nlist.clear();
+        }
+        matched
+    }
+
+    // Follows every epsilon transition from `pc`, adding the threads that
+    // land on a consuming instruction (or `Match`) to `list` in priority order.
+    fn add_thread(
+        &self,
+        list: &mut Threads,
+        pc: usize,
+        pos: usize,
+        haystack: &[u8],
+        mut slots: Vec<Option<usize>>,
+    ) {
+        if !list.visit(pc) {
+            return;
+        }
+        match &self.insts[pc] {
+            Inst::Jmp(to) => self.add_thread(list, *to, pos, haystack, slots),
+            Inst::Split(first, second) => {
+                self.add_thread(list, *first, pos, haystack, slots.clone());
+                self.add_thread(list, *second, pos, haystack, slots);
+            }
+            Inst::Save(slot) => {
+                if *slot < slots.len() {
+                    slots[*slot] = Some(pos);
+                }
+                self.add_thread(list, pc + 1, pos, haystack, slots);
+            }
+            Inst::Assert(look) => {
+                if look.holds(haystack, pos) {
+                    self.add_thread(list, pc + 1, pos, haystack, slots);
+                }
+            }
+            _ => list.threads.push((pc, slots)),
+        }
+    }
+}
+
+struct Threads {
+    threads: Vec<(usize, Vec<Option<usize>>)>,
+    visited: Vec<bool>,
+}
+
+impl Threads {
+    fn new(len: usize) -> Threads {
+        Threads {
+            threads: Vec::new(),
+            visited: vec![false; len],
+        }
+    }
+
+    fn visit(&mut self, pc: usize) -> bool {
+        !std::mem::replace(&mut self.visited[pc], true)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.threads.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.threads.clear();
+        self.visited.iter_mut().for_each(|v| *v = false);
+    }
+}
+
+// Decodes the character starting at `pos`, returning it with its byte length.
+fn decode(haystack: &[u8], pos: usize) -> Option<(char, usize)> {
+    let rest = haystack.get(pos..)?;
+    let first = *rest.first()?;
+    let len = match first {
+        0x00..=0x7F => return Some((first as char, 1)),
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF7 => 4,
+        _ => return Some((char::REPLACEMENT_CHARACTER, 1)),
+    };
+    match rest.get(..len).map(std::str::from_utf8) {
+        Some(Ok(s)) => s.chars().next().map(|c| (c, len)),
+        _ => Some((char::REPLACEMENT_CHARACTER, 1)),
+    }
+}
+
+// Decodes the character that ends right before `pos`.
+fn decode_last(haystack: &[u8], pos: usize) -> Option<char> {
+    let start = pos.checked_sub(1)?;
+    for back in 0..4.min(pos) {
+        let at = start - back;
+        if haystack[at] & 0xC0 != 0x80 {
+            return match decode(haystack, at) {
+                Some((c, len)) if at + len == pos => Some(c),
+                _ => Some(char::REPLACEMENT_CHARACTER),
+            };
+        }
+    }
+    Some(char::REPLACEMENT_CHARACTER)
+}
+
+pub(crate) fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Look {
+    Start,
+    End,
+    LineStart,
+    LineEnd,
+    WordBoundary,
+    NotWordBoundary,
+    // only used for `whole_words`; there is no syntax for them
+    NoWordBefore,
+    NoWordAfter,
+}
+
+impl Look {
+    fn holds(self, haystack: &[u8], pos: usize) -> bool {
+        match self {
+            Look::Start => pos == 0,
+            Look::End => pos == haystack.len(),
+            Look::LineStart => pos == 0 || haystack[pos - 1] == b'\n',
+            Look::LineEnd => pos == haystack.len() || haystack[pos] == b'\n',
+            Look::WordBoundary | Look::NotWordBoundary => {
+                let before = decode_last(haystack, pos).is_some_and(is_word_char);
+                let after = decode(haystack, pos).is_some_and(|(c, _)| is_word_char(c));
+                (before != after) == (self == Look::WordBoundary)
+            }
+            Look::NoWordBefore => !decode_last(haystack, pos).is_some_and(is_word_char),
+            Look::NoWordAfter => !decode(haystack, pos).is_some_and(|(c, _)| is_word_char(c)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Perl {
+    Digit,
+    Word,
+    Space,
+}
+
+impl Perl {
+    fn matches(self, c: char) -> bool {
+        match self {
+            Perl::Digit => c.is_ascii_digit(),
+            Perl::Word => is_word_char(c),
+            Perl::Space => c.is_whitespace(),
+        }
+    }
+}
+
+// The named classes of POSIX brackets, as in `[[:alpha:]_]`. Like grep in
+// the C locale, they only cover ASCII.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Posix {
+    Alnum,
+    Alpha,
+    Blank,
+    Cntrl,
+    Digit,
+    Graph,
+    Lower,
+    Print,
+    Punct,
+    Space,
+    Upper,
+    XDigit,
+}
+
+impl Posix {
+    fn named(name: &str) -> Option<Posix> {
+        Some(match name {
+            "alnum" => Posix::Alnum,
+            "alpha" => Posix::Alpha,
+            "blank" => Posix::Blank,
+            "cntrl" => Posix::Cntrl,
+            "digit" => Posix::Digit,
+            "graph" => Posix::Graph,
+            "lower" => Posix::Lower,
+            "print" => Posix::Print,
+            "punct" => Posix::Punct,
+            "space" => Posix::Space,
+            "upper" => Posix::Upper,
+            "xdigit" => Posix::XDigit,
+            _ => return None,
+        })
+    }
+
+    fn matches(self, c: char) -> bool {
+        match self {
+            Posix::Alnum => c.is_ascii_alphanumeric(),
+            Posix::Alpha => c.is_ascii_alphabetic(),
+            Posix::Blank => c == ' ' || c == '\t',
+            Posix::Cntrl => c.is_ascii_control(),
+            Posix::Digit => c.is_ascii_digit(),
+            Posix::Graph => c.is_ascii_graphic(),
+            Posix::Lower => c.is_ascii_lowercase(),
+            Posix::Print => c.is_ascii_graphic() || c == ' ',
+            Posix::Punct => c.is_ascii_punctuation(),
+            // is_ascii_whitespace leaves out the vertical tab
+            Posix::Space => c.is_ascii_whitespace() || c == '\x0b',
+            Posix::Upper => c.is_ascii_uppercase(),
+            Posix::XDigit => c.is_ascii_hexdigit(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ClassItem {
+    Range(char, char),
+    Perl(Perl, bool),
+    Posix(Posix),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+    case_insensitive: bool,
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        let hit = if self.case_insensitive {
+            case_variants(c).iter().any(|&v| self.matches_exact(v))
+        } else {
+            self.matches_exact(c)
+        };
+        hit != self.negated
+    }
+
+    fn matches_exact(&self, c: char) -> bool {
+        self.items.iter().any(|item| match *item {
+            ClassItem::Range(lo, hi) => lo <= c && c <= hi,
+            ClassItem::Perl(perl, negated) => perl.matches(c) != negated,
+            ClassItem::Posix(posix) => posix.matches(c),
+        })
+    }
+}
+
+// The character itself plus its single-character upper and lower case forms.
+fn case_variants(c: char) -> Vec<char> {
+    let mut variants = vec![c];
+    let mut lower = c.to_lowercase();
+    let mut upper = c.to_uppercase();
+    for mapped in [lower.next(), upper.next()].into_iter().flatten() {
+        if !variants.contains(&mapped) {
+            variants.push(mapped);
+        }
+    }
+    // Multi-character mappings (like 'ß' -> "SS") have no single-char form.
+    if lower.next().is_some() || upper.next().is_some() {
+        variants.truncate(1);
+    }
+    variants
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Empty,
+    Literal(char),
+    Any,
+    Class(Class),
+    Look(Look),
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+}
+
+impl Parser {
+    fn parse(&mut self) -> Result<Node, Error> {
+        let node = self.parse_alternate()?;
+        if self.pos < self.chars.len() {
+            // The only way to stop early is an unbalanced ')'.
+            return Err(Error::new("unopened group", self.pos));
+        }
+        Ok(node)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_alternate(&mut self) -> Result<Node, Error> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.eat('|') {
+            branches.push(self.parse_concat()?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Node::Alternate(branches)
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, Error> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_repeat(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn parse_repeat(&mut self, mut atom: Node) -> Result<Node, Error> {
+        loop {
+            let start = self.pos;
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => match self.parse_counted()? {
+                    Some(bounds) => bounds,
+                    None => return Ok(atom),
+                },
+                _ => return Ok(atom),
+            };
+            if self.pos == start {
+                self.pos += 1;
+            }
+            if matches!(atom, Node::Empty | Node::Look(_)) {
+                return Err(Error::new("repetition operator missing expression", start));
+            }
+            let greedy = !self.eat('?');
+            atom = Node::Repeat {
+                node: Box::new(atom),
+                min,
+                max,
+                greedy,
+            };
+        }
+    }
+
+    // Parses `{n}`, `{n,}` or `{n,m}`. Anything else leaves `{` as a literal.
+    fn parse_counted(&mut self) -> Result<Option<(u32, Option<u32>)>, Error> {
+        let start = self.pos;
+        self.pos += 1;
+        let min = self.parse_number();
+        let max = if self.eat(',') {
+            self.parse_number()
+        } else {
+            min
+        };
+        match (min, self.eat('}')) {
+            (Some(min), true) => {
+                if max.is_some_and(|max| max < min) {
+                    return Err(Error::new("invalid repetition range", start));
+                }
+                if min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT) {
+                    return Err(Error::new("repetition count too large", start));
+                }
+                Ok(Some((min, max)))
+            }
+            _ => {
+                self.pos = start;
+                Ok(None)
+            }
+        }
+    }
+
+    fn parse_number(&mut self) -> Option<u32> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        digits.parse().ok()
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, Error> {
+        let start = self.pos;
+        let c = self.peek().unwrap();
+        self.pos += 1;
+        match c {
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Look(Look::Start)),
+            '$' => Ok(Node::Look(Look::End)),
+            '[' => self.parse_class(start),
+            '\\' => self.parse_escape(),
+            '(' => {
+                let index = if self.eat('?') {
+                    if !self.eat(':') {
+                        return Err(Error::new("unsupported group flag", self.pos));
+                    }
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups - 1)
+                };
+                let inner = self.parse_alternate()?;
+                if !self.eat(')') {
+                    return Err(Error::new("unclosed group", start));
+                }
+                Ok(Node::Group(Box::new(inner), index))
+            }
+            '*' | '+' | '?' => Err(Error::new("repetition operator missing expression", start)),
+            c => Ok(Node::Literal(c)),
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, Error> {
+        let start = self.pos - 1;
+        let Some(c) = self.peek() else {
+            return Err(Error::new("trailing backslash", start));
+        };
+        self.pos += 1;
+        let perl = |perl, negated| {
+            Node::Class(Class {
+                items: vec![ClassItem::Perl(perl, negated)],
+                negated: false,
+                case_insensitive: false,
+            })
+        };
+        Ok(match c {
+            'd' => perl(Perl::Digit, false),
+            'D' => perl(Perl::Digit, true),
+            'w' => perl(Perl::Word, false),
+            'W' => perl(Perl::Word, true),
+            's' => perl(Perl::Space, false),
+            'S' => perl(Perl::Space, true),
+            'b' => Node::Look(Look::WordBoundary),
+            'B' => Node::Look(Look::NotWordBoundary),
+            _ => Node::Literal(self.escaped_literal(c, start)?),
+        })
+    }
+
+    fn escaped_literal(&self, c: char, start: usize) -> Result<char, Error> {
+        match c {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            c if c.is_ascii_alphanumeric() => {
+                Err(Error::new(format!("unknown escape sequence \\{c}"), start))
+            }
+            c => Ok(c),
+        }
+    }
+
+    fn parse_class(&mut self, start: usize) -> Result<Node, Error> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(Error::new("unclosed character class", start));
+            };
+            self.pos += 1;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            if c == '['
+                && self.peek() == Some(':')
+                && let Some(posix) = self.posix_class()?
+            {
+                items.push(ClassItem::Posix(posix));
+                continue;
+            }
+            let lo = if c == '\\' {
+                let Some(e) = self.peek() else {
+                    return Err(Error::new("unclosed character class", start));
+                };
+                self.pos += 1;
+                let perl = match e {
+                    'd' | 'D' => Some(Perl::Digit),
+                    'w' | 'W' => Some(Perl::Word),
+                    's' | 'S' => Some(Perl::Space),
+                    _ => None,
+                };
+                if let Some(perl) = perl {
+                    items.push(ClassItem::Perl(perl, e.is_ascii_uppercase()));
+                    continue;
+                }
+                self.escaped_literal(e, self.pos - 2)?
+            } else {
+                c
+            };
+            // A '-' right before ']' is a literal dash, not a range.
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&n| n != ']') {
+                self.pos += 1;
+                let mut hi = self.chars[self.pos];
+                self.pos += 1;
+                if hi == '\\' {
+                    let Some(e) = self.peek() else {
+                        return Err(Error::new("unclosed character class", start));
+                    };
+                    self.pos += 1;
+                    hi = self.escaped_literal(e, self.pos - 2)?;
+                }
+                if hi < lo {
+                    return Err(Error::new("invalid class range", start));
+                }
+                items.push(ClassItem::Range(lo, hi));
+            } else {
+                items.push(ClassItem::Range(lo, lo));
+            }
+        }
+        Ok(Node::Class(Class {
+            items,
+            negated,
+            case_insensitive: false,
+        }))
+    }
+
+    // After the '[' of a class such as `[:alpha:]`, which must be one of the
+    // POSIX names. Without the closing ":]" the '[' is only a character.
+    fn posix_class(&mut self) -> Result<Option<Posix>, Error> {
+        let start = self.pos - 1;
+        let name: String = self.chars[self.pos + 1..]
+            .iter()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .collect();
+        let end = self.pos + 1 + name.len();
+        if self.chars.get(end..end + 2) != Some(&[':', ']']) {
+            return Ok(None);
+        }
+        let posix = Posix::named(&name)
+            .ok_or_else(|| Error::new(format!("unknown POSIX class [:{name}:]"), start))?;
+        self.pos = end + 2;
+        Ok(Some(posix))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Look),
+    Split(usize, usize),
+    Jmp(usize),
+    Save(usize),
+    Match,
+}
+
+struct Compiler {
+    insts: Vec<Inst>,
+    case_insensitive: bool,
+    multi_line: bool,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> Result<usize, Error> {
+        if self.insts.len() >= MAX_INSTS {
+            return Err(Error::new("pattern too large", 0));
+        }
+        self.insts.push(inst);
+        Ok(self.insts.len() - 1)
+    }
+
+    fn compile(&mut self, node: &Node) -> Result<(), Error> {
+        match node {
+            Node::Empty => {}
+            Node::Literal(c) => {
+                let variants = case_variants(*c);
+                if self.case_insensitive && variants.len() > 1 {
+                    let items = variants.iter().map(|&v| ClassItem::Range(v, v)).collect();
+                    self.push(Inst::Class(Class {
+                        items,
+                        negated: false,
+                        case_insensitive: false,
+                    }))?;
+                } else {
+                    self.push(Inst::Char(*c))?;
+                }
+            }
+            Node::Any => {
+                self.push(Inst::Any)?;
+            }
+            Node::Class(class) => {
+                let mut class = class.clone();
+                class.case_insensitive = self.case_insensitive;
+                self.push(Inst::Class(class))?;
+            }
+            Node::Look(look) => {
+                let look = match look {
+                    Look::Start if self.multi_line => Look::LineStart,
+                    Look::End if self.multi_line => Look::LineEnd,
+                    look => *look,
+                };
+                self.push(Inst::Assert(look))?;
+            }
+            Node::Group(inner, index) => match index {
+                Some(index) => {
+                    self.push(Inst::Save(index * 2))?;
+                    self.compile(inner)?;
+                    self.push(Inst::Save(index * 2 + 1))?;
+                }
+                None => self.compile(inner)?,
+            },
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node)?;
+                }
+            }
+            Node::Alternate(branches) => {
+                let mut jumps = Vec::new();
+                for (i, branch) in branches.iter().enumerate() {
+                    if i + 1 < branches.len() {
+                        let split = self.push(Inst::Split(0, 0))?;
+                        self.compile(branch)?;
+                        jumps.push(self.push(Inst::Jmp(0))?);
+                        let next = self.insts.len();
+                        self.insts[split] = Inst::Split(split + 1, next);
+                    } else {
+                        self.compile(branch)?;
+                    }
+                }
+                let end = self.insts.len();
+                for jump in jumps {
+                    self.insts[jump] = Inst::Jmp(end);
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => self.compile_repeat(node, *min, *max, *greedy)?,
+        }
+        Ok(())
+    }
+
+    fn compile_repeat(
+        &mut self,
+        node: &Node,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    ) -> Result<(), Error> {
+        for _ in 0..min {
+            self.compile(node)?;
+        }
+        match max {
+            // x* : L1: split L2, L3; L2: x; jmp L1; L3:
+            None => {
+                let split = self.push(Inst::Split(0, 0))?;
+                self.compile(node)?;
+                self.push(Inst::Jmp(split))?;
+                let end = self.insts.len();
+                self.insts[split] = self.split(split + 1, end, greedy);
+            }
+            // x{0,n} : n nested optional copies, each jumping to the end.
+            Some(max) => {
+                let mut splits = Vec::new();
+                for _ in min..max {
+                    splits.push(self.push(Inst::Split(0, 0))?);
+                    self.compile(node)?;
+                }
+                let end = self.insts.len();
+                for split in splits {
+                    self.insts[split] = self.split(split + 1, end, greedy);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn split(&self, take: usize, skip: usize, greedy: bool) -> Inst {
+        if greedy {
+            Inst::Split(take, skip)
+        } else {
+            Inst::Split(skip, take)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find<'a>(pattern: &str, text: &'a str) -> Option<&'a str> {
+        let re = Regex::new(pattern).unwrap();
+        re.find(text).map(|m| &text[m])
+    }
+
+    #[test]
+    fn literals_classes_and_repetition() {
+        assert_eq!(Some("fn main("), find(r"fn \w+\(", "pub fn main() {}"));
+        assert_eq!(
+            Some("2024-01-31"),
+            find(r"\d{4}-\d\d-\d+", "at 2024-01-31 12:00")
+        );
+        assert_eq!(Some("cab"), find("[a-c]+", "xxcabxx"));
+        assert_eq!(Some("xy"), find("[^a-c]+", "xycab"));
+        assert_eq!(None, find("^b", "ab"));
+        assert_eq!(Some("b"), find("b$", "ab"));
+    }
+
+    #[test]
+    fn posix_bracket_classes() {
+        assert_eq!(Some("hello"), find("[[:alpha:]]+", "1] :]] hello"));
+        assert_eq!(Some("x_1"), find("[[:alpha:]_][[:alnum:]_]*", "-x_1-"));
+        assert_eq!(Some("\t "), find("[[:space:]]+", "a\t b"));
+        assert_eq!(Some("0xFF"), find("0x[[:xdigit:]]+", "= 0xFFg"));
+        assert_eq!(Some("b1"), find("[^[:upper:][:punct:]]+", "A,b1"));
+        // a '[' without ":]" after it is an ordinary character
+        assert_eq!(Some("[:"), find("[[:]+", "a[:"));
+        let re = Regex::builder("[[:upper:]]+")
+            .case_insensitive(true)
+            .build();
+        assert_eq!(Some(0..3), re.unwrap().find("abc"));
+        let err = Regex::new("[[:alhpa:]]").unwrap_err();
+        assert_eq!(
+            "invalid regex at position 1: unknown POSIX class [:alhpa:]",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn leftmost_first_alternation_and_laziness() {
+        assert_eq!(Some("sam"), find("sam|samwise", "samwise"));
+        assert_eq!(Some("<a><b>"), find("<.+>", "<a><b>"));
+        assert_eq!(Some("<a>"), find("<.+?>", "<a><b>"));
+        assert_eq!(Some("word"), find(r"\bword\b", "swords word"));
+    }
+
+    #[test]
+    fn case_insensitive_option() {
+        let re = Regex::builder("rUsT[a-z]*")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert_eq!(Some(1..5), re.find("Trust me"));
+        assert!(re.is_match("RUSTY"));
+    }
+
+    #[test]
+    fn whole_words_try_every_alternative() {
+        let find = |pattern: &str, text: &'static str| {
+            let re = Regex::builder(pattern).whole_words(true).build().unwrap();
+            re.find(text).map(|span| &text[span])
+        };
+        assert_eq!(Some("ab"), find("a|ab", "ab"));
+        assert_eq!(Some("abc"), find("ab|abc", "abc"));
+        assert_eq!(Some("foo"), find("fo|foo", "foo bar"));
+        // punctuation at the edges of a match is fine
+        assert_eq!(Some("(duct)"), find(r"\(duct\)", "a (duct) b"));
+        assert_eq!(None, find("duct", "productive"));
+    }
+
+    #[test]
+    fn multi_line_anchors() {
+        let text = "fn main(\n    x: u8,\n) {}";
+        let re = Regex::builder(r"^\s+x").multi_line(true).build().unwrap();
+        assert_eq!(Some(9..14), re.find(text));
+        assert_eq!(None, Regex::new(r"^\s+x").unwrap().find(text));
+        let re = Regex::builder(r"\($").multi_line(true).build().unwrap();
+        assert_eq!(Some(7..8), re.find(text));
+        // `.` stays on its line
+        assert_eq!(None, Regex::new(r"main.*x").unwrap().find(text));
+        assert_eq!(Some(3..14), Regex::new(r"main\(\s+x").unwrap().find(text));
+    }
+
+    #[test]
+    fn invalid_patterns_report_errors() {
+        assert!(Regex::new("(abc").is_err());
+        assert!(Regex::new("abc)").is_err());
+        assert!(Regex::new("[abc").is_err());
+        assert!(Regex::new("*a").is_err());
+        assert!(Regex::new(r"\q").is_err());
+        let err = Regex::new("a{3,1}").unwrap_err();
+        assert_eq!(
+            "invalid regex at position 1: invalid repetition range",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn empty_loops_terminate() {
+        assert_eq!(Some(""), find("(a*)*", "bbb"));
+        assert_eq!(Some("aaa"), find("(a|)+", "aaa"));
+    }
+}
@@ -0,0 +1,190 @@
+// In-place search and replace for `--write`.
+//
+// A file is read whole, every match on every line is replaced using the same
+// pattern the search uses, and the result is written to a temporary file
+// next to the original that is then renamed over it. The rename is atomic, so
+// other programs see either the old contents or the new, never a mix, and a
+// failure halfway leaves the original untouched. With `--dry-run` nothing is
+// written; the changes are printed as a diff instead.
+use std::fs::{self, File};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::process;
+
+use crate::searcher::find_all;
+use crate::{Config, Pattern, Printer, replace_all, trim_line_ending, walk};
+
+// One line that --write changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change<'a> {
+    pub line_number: usize,
+    pub old: &'a str,
+    pub new: String,
+}
+
+// Replaces the matches in the file at `path`, whose contents `reader`
+// yields. Returns the number of lines that matched. Binary files are left
+// alone unless `--text` is given, and text that is not UTF-8 is an
+// `InvalidData` error, as rewriting it would mangle the invalid bytes.
+pub(crate) fn rewrite<R: BufRead, W: Write>(
+    config: &Config,
+    pattern: &Pattern,
+    printer: &mut Printer<W>,
+    path: &Path,
+    mut reader: R,
+) -> io::Result<usize> {
+    if !config.text && walk::is_binary(reader.fill_buf()?) {
+        return Ok(0);
+    }
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+    let replacement = config.replace.as_deref().unwrap_or_default();
+
+    let mut rewritten = String::with_capacity(contents.len());
+    let mut changes = Vec::new();
+    let mut matched = 0;
+    for (index, raw) in contents.split_inclusive('\n').enumerate() {
+        let line = trim_line_ending(raw);
+        let Some(first) = pattern.find(line) else {
+            rewritten.push_str(raw);
+            continue;
+        };
+        matched += 1;
+        let new = replace_all(line, &find_all(pattern, line, first), replacement);
+        rewritten.push_str(&new);
+        // keep the line ending, "\r\n" included
+        rewritten.push_str(&raw[line.len()..]);
+        if new != line {
+            changes.push(Change {
+                line_number: index + 1,
+                old: line,
+                new,
+            });
+        }
+    }
+
+    if changes.is_empty() {
+        return Ok(matched);
+    }
+    if config.dry_run {
+        printer.diff(&changes)?;
+    } else {
+        write_atomically(path, rewritten.as_bytes())?;
+    }
+    Ok(matched)
+}
+
+fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    // the temporary file must be on the same file system for the rename
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = dir.join(format!(".{name}.mini-grep-{}", process::id()));
+    let permissions = fs::metadata(path)?.permissions();
+
+    let written = File::create_new(&temp).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::set_permissions(&temp, permissions)?;
+        fs::rename(&temp, path)
+    });
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Searcher;
+    use crate::tempdir::TempDir;
+    use std::io::Cursor;
+
+    fn rewrite_str(config: &Config, contents: &str) -> (usize, String) {
+        let pattern = Pattern::new(config).unwrap();
+        let mut printer = Printer::new(config, Vec::new());
+        printer.begin(Path::new("notes.txt"));
+        let path = Path::new("unused");
+        let matched = rewrite(config, &pattern, &mut printer, path, Cursor::new(contents)).unwrap();
+        (matched, String::from_utf8(printer.into_inner()).unwrap())
+    }
+
+    #[test]
+    fn dry_run_prints_a_diff() {
+        let config = Config {
+            patterns: vec![String::from("colour")],
+            replace: Some(String::from("color")),
+            write: true,
+            dry_run: true,
+            ..Config::default()
+        };
+        let (matched, diff) = rewrite_str(&config, "colour, colour\r\nred\ncolour\n");
+        assert_eq!(2, matched);
+        let expected = "--- a/notes.txt\n+++ b/notes.txt\n\
+            @@ -1 +1 @@\n-colour, colour\n+color, color\n\
+            @@ -3 +3 @@\n-colour\n+color\n";
+        assert_eq!(expected, diff);
+    }
+
+    #[test]
+    fn empty_matches_are_replaced_alike_everywhere() {
+        let search = |config: &Config| {
+            let pattern = Pattern::new(config).unwrap();
+            let mut printer = Printer::new(config, Vec::new());
+            printer.begin(Path::new("notes.txt"));
+            Searcher::from(config)
+                .search_slice(&pattern, b"foo\n", &mut printer)
+                .unwrap();
+            String::from_utf8(printer.into_inner()).unwrap()
+        };
+        for (regex, expected) in [("o?", "XfXX"), ("^", "Xfoo"), ("$", "fooX")] {
+            let mut config = Config {
+                patterns: vec![String::from(regex)],
+                use_regex: true,
+                replace: Some(String::from("X")),
+                ..Config::default()
+            };
+            assert_eq!(format!("{expected}\n"), search(&config));
+            config.json = true;
+            let json = search(&config);
+            assert!(
+                json.contains(&format!(r#""replacement":"{expected}","#)),
+                "{json}"
+            );
+            config.json = false;
+            config.write = true;
+            config.dry_run = true;
+            let (_, diff) = rewrite_str(&config, "foo\n");
+            assert!(diff.ends_with(&format!("-foo\n+{expected}\n")), "{diff}");
+        }
+    }
+
+    #[test]
+    fn rewrites_files_in_place() {
+        let dir = TempDir::new("replace");
+        let path = dir.join("a.txt");
+        fs::write(&path, "one fish\r\ntwo fish\nred\n").unwrap();
+
+        let config = Config {
+            patterns: vec![String::from("fish")],
+            replace: Some(String::from("cat")),
+            write: true,
+            ..Config::default()
+        };
+        let pattern = Pattern::new(&config).unwrap();
+        let mut printer = Printer::new(&config, Vec::new());
+        let reader = io::BufReader::new(File::open(&path).unwrap());
+        let matched = rewrite(&config, &pattern, &mut printer, &path, reader).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        let entries = fs::read_dir(&dir).unwrap().count();
+
+        assert_eq!(2, matched);
+        assert_eq!("one cat\r\ntwo cat\nred\n", contents);
+        // no temporary file is left behind, and nothing is printed
+        assert_eq!(1, entries);
+        assert!(printer.into_inner().is_empty());
+    }
+}
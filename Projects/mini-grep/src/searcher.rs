@@ -0,0 +1,675 @@
+// The search loop, open to matchers and result handlers from outside.
+//
+// A `Matcher` finds matches in a line; a `Searcher` splits files, readers or
+// buffers into lines, hands each to the matcher and passes what it selects to
+// a `Sink`. The command line is built from the same pieces: its patterns are
+// a matcher and its `Printer` is a sink, so anything that implements the two
+// traits gets binary detection, lossy decoding of invalid UTF-8 and the
+// whole-buffer fast path for free.
+//
+// In multiline mode the matcher sees the whole input at once, as its lines
+// joined with "\n", so a match can span lines. Each run of lines that
+// matches cover is then handed to the sink as a single `Match`.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::{
+    Config, Finder, Match, Mmap, OutputMode, Regex, memchr, select, trim_line_ending, walk,
+};
+
+// Finds matches in one line.
+pub trait Matcher {
+    // The first match that starts at or after byte `start` of `haystack`, a
+    // line without its line ending. The searcher always passes valid UTF-8:
+    // invalid bytes have been replaced with U+FFFD.
+    fn find_at(&self, haystack: &[u8], start: usize) -> Option<Range<usize>>;
+
+    fn find(&self, haystack: &[u8]) -> Option<Range<usize>> {
+        self.find_at(haystack, 0)
+    }
+
+    // `find_at` on a haystack that is known to be UTF-8 already, which is
+    // how the searcher calls it. Matchers that work on `str` can override
+    // it to skip checking the haystack again on every call.
+    fn find_str_at(&self, haystack: &str, start: usize) -> Option<Range<usize>> {
+        self.find_at(haystack.as_bytes(), start)
+    }
+
+    // A literal that every match contains, if there is one. Given a whole
+    // buffer, the searcher looks for it first and skips the lines without.
+    fn literal(&self) -> Option<&Finder> {
+        None
+    }
+
+    // For approximate matchers, how many edits the match at `span` is away
+    // from the pattern.
+    fn distance(&self, _haystack: &[u8], _span: &Range<usize>) -> Option<usize> {
+        None
+    }
+}
+
+impl Matcher for Finder {
+    fn find_at(&self, haystack: &[u8], start: usize) -> Option<Range<usize>> {
+        let at = start + self.find(&haystack[start..])?;
+        Some(at..at + self.needle().len())
+    }
+
+    fn literal(&self) -> Option<&Finder> {
+        Some(self)
+    }
+}
+
+impl Matcher for Regex {
+    fn find_at(&self, haystack: &[u8], start: usize) -> Option<Range<usize>> {
+        Regex::find_at(self, haystack, start)
+    }
+}
+
+// Receives the results of a search. Each method returns whether to go on.
+pub trait Sink {
+    // A selected line with every match in it, in order; lines selected by
+    // `invert_match` have none.
+    fn matched(&mut self, m: &Match, spans: &[Range<usize>]) -> io::Result<bool>;
+
+    // A line that was not selected. Only called when the searcher is asked
+    // for context, and then for every such line, so the sink can keep what
+    // it needs.
+    fn context(&mut self, _line_number: usize, _line: &str) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    // Takes the place of `matched` once a NUL byte shows the input is
+    // binary. The default stops the search.
+    fn binary(&mut self, _m: &Match, _spans: &[Range<usize>]) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    // The search is over, having selected `count` lines.
+    fn finish(&mut self, _count: usize) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Any closure taking the arguments of `Sink::matched` is a sink.
+impl<F: FnMut(&Match, &[Range<usize>]) -> io::Result<bool>> Sink for F {
+    fn matched(&mut self, m: &Match, spans: &[Range<usize>]) -> io::Result<bool> {
+        self(m, spans)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Searcher {
+    invert_match: bool,
+    text: bool,
+    context: bool,
+    multiline: bool,
+}
+
+// One line, with where it is and whether the input is binary by then.
+struct Line<'a> {
+    number: usize,
+    offset: usize,
+    raw: &'a [u8],
+    binary: bool,
+}
+
+impl Searcher {
+    pub fn new() -> Searcher {
+        Searcher::default()
+    }
+
+    // Selects the lines that do not match instead.
+    pub fn invert_match(mut self, yes: bool) -> Searcher {
+        self.invert_match = yes;
+        self
+    }
+
+    // Treats binary input as text, so `Sink::binary` is never called.
+    pub fn text(mut self, yes: bool) -> Searcher {
+        self.text = yes;
+        self
+    }
+
+    // Passes the lines that are not selected to `Sink::context`.
+    pub fn context(mut self, yes: bool) -> Searcher {
+        self.context = yes;
+        self
+    }
+
+    // Lets matches span lines; the input is then read whole.
+    pub fn multiline(mut self, yes: bool) -> Searcher {
+        self.multiline = yes;
+        self
+    }
+
+    pub fn search_path<M, S>(
+        &self,
+        matcher: &M,
+        path: impl AsRef<Path>,
+        sink: &mut S,
+    ) -> io::Result<usize>
+    where
+        M: Matcher + ?Sized,
+        S: Sink + ?Sized,
+    {
+        let file = File::open(path)?;
+        self.search_file(matcher, &file, sink)
+    }
+
+    // Searches a whole file at once when it can skip lines, memory-mapping
+    // it if it is large, and streams it otherwise.
+    pub fn search_file<M, S>(&self, matcher: &M, file: &File, sink: &mut S) -> io::Result<usize>
+    where
+        M: Matcher + ?Sized,
+        S: Sink + ?Sized,
+    {
+        if (self.multiline || self.literal(matcher).is_some())
+            && let Some(contents) = Mmap::open(file)?
+        {
+            return self.search_slice(matcher, &contents, sink);
+        }
+        self.search_reader(matcher, BufReader::new(file), sink)
+    }
+
+    // Searches `reader` one line at a time, so memory use is bounded by the
+    // longest line however large the input is (unless in multiline mode,
+    // which reads it whole). Returns the number of lines selected before the
+    // sink asked to stop.
+    pub fn search_reader<M, S>(
+        &self,
+        matcher: &M,
+        mut reader: impl BufRead,
+        sink: &mut S,
+    ) -> io::Result<usize>
+    where
+        M: Matcher + ?Sized,
+        S: Sink + ?Sized,
+    {
+        let mut buf = Vec::new();
+        if self.multiline {
+            reader.read_to_end(&mut buf)?;
+            return self.search_multiline(matcher, &buf, sink);
+        }
+        let mut offset = 0;
+        let mut number = 0;
+        let mut count = 0;
+        // like grep, look at the first block and at every line read after it
+        let mut binary = !self.text && walk::is_binary(reader.fill_buf()?);
+        loop {
+            buf.clear();
+            let read = reader.read_until(b'\n', &mut buf)?;
+            if read == 0 {
+                break;
+            }
+            number += 1;
+            binary |= !self.text && buf.contains(&0);
+            let line = Line {
+                number,
+                offset,
+                raw: &buf,
+                binary,
+            };
+            if !self.search_line(matcher, sink, &mut count, line)? {
+                break;
+            }
+            offset += read;
+        }
+        sink.finish(count)?;
+        Ok(count)
+    }
+
+    // Searches a buffer. When the matcher has a literal, it is looked for in
+    // the whole buffer and only the lines around what is found are split
+    // off, so lines without it cost next to nothing.
+    pub fn search_slice<M, S>(&self, matcher: &M, buf: &[u8], sink: &mut S) -> io::Result<usize>
+    where
+        M: Matcher + ?Sized,
+        S: Sink + ?Sized,
+    {
+        if self.multiline {
+            return self.search_multiline(matcher, buf, sink);
+        }
+        let Some(finder) = self.literal(matcher) else {
+            return self.search_reader(matcher, buf, sink);
+        };
+        let binary_start = !self.text && walk::is_binary(buf);
+        let nul = if self.text {
+            None
+        } else {
+            memchr::memchr(0, buf)
+        };
+        let mut count = 0;
+        for (number, offset, raw) in CandidateLines::new(finder, buf) {
+            // as if read line by line: binary once a NUL has gone by
+            let binary = binary_start || nul.is_some_and(|at| at < offset + raw.len());
+            let line = Line {
+                number,
+                offset,
+                raw,
+                binary,
+            };
+            if !self.search_line(matcher, sink, &mut count, line)? {
+                break;
+            }
+        }
+        sink.finish(count)?;
+        Ok(count)
+    }
+
+    // The literal that lets whole buffers be searched: lines without it are
+    // never selected, unless by -v, and are not wanted as context. Lines are
+    // decoded lossily, so a literal with U+FFFD may match where the bytes
+    // do not.
+    fn literal<'m, M: Matcher + ?Sized>(&self, matcher: &'m M) -> Option<&'m Finder> {
+        if self.invert_match || self.context || self.multiline {
+            return None;
+        }
+        let replacement = "\u{FFFD}".as_bytes();
+        matcher
+            .literal()
+            .filter(|finder| !finder.needle().windows(3).any(|w| w == replacement))
+    }
+
+    fn search_line<M, S>(
+        &self,
+        matcher: &M,
+        sink: &mut S,
+        count: &mut usize,
+        line: Line,
+    ) -> io::Result<bool>
+    where
+        M: Matcher + ?Sized,
+        S: Sink + ?Sized,
+    {
+        let text = String::from_utf8_lossy(line.raw);
+        let text = trim_line_ending(&text);
+        let Some(span) = select(matcher.find_str_at(text, 0), self.invert_match) else {
+            if self.context && !line.binary {
+                return sink.context(line.number, text);
+            }
+            return Ok(true);
+        };
+        *count += 1;
+        let spans = if self.invert_match {
+            Vec::new()
+        } else {
+            find_all(matcher, text, span.clone())
+        };
+        let m = Match {
+            line_number: line.number,
+            byte_offset: line.offset,
+            distance: matcher.distance(text.as_bytes(), &span),
+            span,
+            line: text,
+        };
+        if line.binary {
+            sink.binary(&m, &spans)
+        } else {
+            sink.matched(&m, &spans)
+        }
+    }
+}
+
+// A whole input for multiline search: its lines without their endings,
+// joined with "\n" (so "\r\n" reads as "\n"), and where each line starts
+// in that text and in the input.
+struct Joined {
+    text: String,
+    starts: Vec<(usize, usize)>,
+}
+
+impl Joined {
+    fn new(buf: &[u8]) -> Joined {
+        let mut text = String::with_capacity(buf.len());
+        let mut starts = Vec::new();
+        let mut offset = 0;
+        for raw in buf.split_inclusive(|&b| b == b'\n') {
+            starts.push((text.len(), offset));
+            offset += raw.len();
+            text.push_str(trim_line_ending(&String::from_utf8_lossy(raw)));
+            text.push('\n');
+        }
+        Joined { text, starts }
+    }
+
+    // Lines `lines` as one string, without the final newline.
+    fn lines(&self, lines: Range<usize>) -> &str {
+        let end = self.starts.get(lines.end).map_or(self.text.len(), |s| s.0);
+        &self.text[self.starts[lines.start].0..end - 1]
+    }
+
+    // The line that byte `at` of the text is on.
+    fn line_of(&self, at: usize) -> usize {
+        self.starts.partition_point(|&(start, _)| start <= at) - 1
+    }
+
+    // The next run of lines, from line `from` on, that matches cover: which
+    // lines they are, and the matches relative to the start of the first.
+    // Matches that start on a line the run already covers extend it.
+    fn next_block<M: Matcher + ?Sized>(
+        &self,
+        matcher: &M,
+        from: usize,
+    ) -> Option<(Range<usize>, Vec<Range<usize>>)> {
+        let text = &self.text[..];
+        let first = matcher.find_str_at(text, self.starts.get(from)?.0)?;
+        // nothing follows the newline after the last line
+        if first.start >= text.len() {
+            return None;
+        }
+        // a match that ends with a newline stays on the line it ends
+        let last_line = |span: &Range<usize>| self.line_of(span.end.max(span.start + 1) - 1);
+        let start_line = self.line_of(first.start);
+        let mut end_line = last_line(&first);
+        let mut spans = vec![first];
+        while let Some(span) = spans.last().filter(|span| !span.is_empty()) {
+            let covered = self.starts.get(end_line + 1).map_or(text.len(), |s| s.0);
+            match matcher.find_str_at(text, span.end) {
+                Some(next) if next.start < covered => {
+                    end_line = end_line.max(last_line(&next));
+                    spans.push(next);
+                }
+                _ => break,
+            }
+        }
+        let base = self.starts[start_line].0;
+        let len = self.lines(start_line..end_line + 1).len();
+        let spans = spans
+            .into_iter()
+            .map(|span| span.start - base..(span.end - base).min(len))
+            .collect();
+        Some((start_line..end_line + 1, spans))
+    }
+}
+
+impl Searcher {
+    // Searches all of `buf` at once, so that matches can span lines. Counts
+    // every line a match covers as selected.
+    fn search_multiline<M, S>(&self, matcher: &M, buf: &[u8], sink: &mut S) -> io::Result<usize>
+    where
+        M: Matcher + ?Sized,
+        S: Sink + ?Sized,
+    {
+        let joined = Joined::new(buf);
+        let binary = !self.text && memchr::memchr(0, buf).is_some();
+        let mut count = 0;
+        let mut block = joined.next_block(matcher, 0);
+        let mut line = 0;
+        while line < joined.starts.len() {
+            let (lines, spans) = match &block {
+                Some((lines, spans)) if lines.start == line => (lines.clone(), spans.clone()),
+                // a line that no match covers
+                _ => (line..line + 1, Vec::new()),
+            };
+            let covered = !spans.is_empty();
+            line = lines.end;
+            if covered {
+                block = joined.next_block(matcher, line);
+            }
+            let number = lines.start + 1;
+            let text = joined.lines(lines.clone());
+            if covered == self.invert_match {
+                if self.context && !binary {
+                    for (i, text) in text.split('\n').enumerate() {
+                        if !sink.context(number + i, text)? {
+                            break;
+                        }
+                    }
+                }
+                continue;
+            }
+            count += lines.len();
+            let span = spans.first().cloned().unwrap_or(0..0);
+            let m = Match {
+                line_number: number,
+                byte_offset: joined.starts[lines.start].1,
+                distance: matcher.distance(text.as_bytes(), &span),
+                span,
+                line: text,
+            };
+            let go_on = if binary {
+                sink.binary(&m, &spans)?
+            } else {
+                sink.matched(&m, &spans)?
+            };
+            if !go_on {
+                break;
+            }
+        }
+        sink.finish(count)?;
+        Ok(count)
+    }
+}
+
+// The searcher the command line uses for a config.
+impl From<&Config> for Searcher {
+    fn from(config: &Config) -> Searcher {
+        let lines = config.output == OutputMode::Lines;
+        Searcher::new()
+            .invert_match(config.invert_match)
+            .text(config.text)
+            .context(lines && (config.before_context > 0 || config.after_context > 0))
+            .multiline(config.multiline)
+    }
+}
+
+// Every match in `line`, given the first one. After an empty match the
+// search goes on one character further, and an empty match right where the
+// previous one ended is skipped, so "o?" finds "", "o" and "o" in "foo".
+pub fn find_all<M: Matcher + ?Sized>(
+    matcher: &M,
+    line: &str,
+    first: Range<usize>,
+) -> Vec<Range<usize>> {
+    let mut spans: Vec<Range<usize>> = Vec::new();
+    let mut next = Some(first);
+    while let Some(span) = next.take() {
+        let mut at = span.end;
+        if span.is_empty() {
+            if spans.last().is_none_or(|last| last.end != at) {
+                spans.push(span);
+            }
+            let Some(c) = line[at..].chars().next() else {
+                break;
+            };
+            at += c.len_utf8();
+        } else {
+            spans.push(span);
+        }
+        next = matcher.find_str_at(line, at);
+    }
+    spans
+}
+
+// The lines of a buffer that contain a needle, as (line number, byte offset,
+// line with its ending). Newlines are only counted, never searched for, in
+// the stretches between hits.
+pub(crate) struct CandidateLines<'a> {
+    finder: &'a Finder,
+    buf: &'a [u8],
+    // where the next line starts
+    at: usize,
+    // the newlines before `counted` have been counted
+    counted: usize,
+    newlines: usize,
+}
+
+impl<'a> CandidateLines<'a> {
+    pub(crate) fn new(finder: &'a Finder, buf: &'a [u8]) -> CandidateLines<'a> {
+        CandidateLines {
+            finder,
+            buf,
+            at: 0,
+            counted: 0,
+            newlines: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for CandidateLines<'a> {
+    type Item = (usize, usize, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (at, buf) = (self.at, self.buf);
+        if at >= buf.len() {
+            return None;
+        }
+        let found = at + self.finder.find(&buf[at..])?;
+        let start = memchr::memrchr(b'\n', &buf[at..found]).map_or(at, |i| at + i + 1);
+        let end = memchr::memchr(b'\n', &buf[found..]).map_or(buf.len(), |i| found + i + 1);
+        self.newlines += memchr::count(b'\n', &buf[self.counted..start]);
+        self.counted = start;
+        self.at = end;
+        Some((self.newlines + 1, start, &buf[start..end]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A matcher from outside the crate might look like this: runs of digits.
+    struct Digits;
+
+    impl Matcher for Digits {
+        fn find_at(&self, haystack: &[u8], start: usize) -> Option<Range<usize>> {
+            let from = start + haystack[start..].iter().position(u8::is_ascii_digit)?;
+            let len = haystack[from..]
+                .iter()
+                .take_while(|b| b.is_ascii_digit())
+                .count();
+            Some(from..from + len)
+        }
+    }
+
+    #[test]
+    fn custom_matcher_and_closure_sink() {
+        let mut found = Vec::new();
+        let mut sink = |m: &Match, spans: &[Range<usize>]| {
+            let numbers: Vec<&str> = spans.iter().map(|s| &m.line[s.clone()]).collect();
+            found.push(format!("{}: {}", m.line_number, numbers.join(",")));
+            Ok(true)
+        };
+        let input = "no digits\nroom 101, floor 3\r\nvoid\n7";
+        let count = Searcher::new()
+            .search_reader(&Digits, input.as_bytes(), &mut sink)
+            .unwrap();
+        assert_eq!(2, count);
+        assert_eq!(vec!["2: 101,3", "4: 7"], found);
+    }
+
+    // Only takes text that is known to be UTF-8.
+    struct StrOnly;
+
+    impl Matcher for StrOnly {
+        fn find_at(&self, _haystack: &[u8], _start: usize) -> Option<Range<usize>> {
+            panic!("searched bytes that are UTF-8 already");
+        }
+
+        fn find_str_at(&self, haystack: &str, start: usize) -> Option<Range<usize>> {
+            let at = start + haystack[start..].find("x\ny")?;
+            Some(at..at + 3)
+        }
+    }
+
+    #[test]
+    fn text_is_passed_as_str() {
+        let input = "x\ny\nx\nz\nx\ny\n";
+        let mut found = Vec::new();
+        let mut sink = |m: &Match, _: &[Range<usize>]| {
+            found.push(m.line_number);
+            Ok(true)
+        };
+        let searcher = Searcher::new().multiline(true);
+        assert_eq!(
+            4,
+            searcher
+                .search_slice(&StrOnly, input.as_bytes(), &mut sink)
+                .unwrap()
+        );
+        assert_eq!(vec![1, 5], found);
+        let count =
+            Searcher::new().search_slice(&StrOnly, b"x\n", &mut |_: &Match, _: &[Range<usize>]| {
+                Ok(true)
+            });
+        assert_eq!(0, count.unwrap());
+    }
+
+    // Collects everything a sink is told, to compare searches.
+    #[derive(Default)]
+    struct Log(Vec<String>);
+
+    impl Sink for Log {
+        fn matched(&mut self, m: &Match, spans: &[Range<usize>]) -> io::Result<bool> {
+            self.0.push(format!(
+                "{}@{}: {} {spans:?}",
+                m.line_number, m.byte_offset, m.line
+            ));
+            Ok(true)
+        }
+
+        fn context(&mut self, line_number: usize, line: &str) -> io::Result<bool> {
+            self.0.push(format!("{line_number}- {line}"));
+            Ok(true)
+        }
+
+        fn binary(&mut self, m: &Match, _spans: &[Range<usize>]) -> io::Result<bool> {
+            self.0.push(format!("binary at {}", m.line_number));
+            Ok(false)
+        }
+
+        fn finish(&mut self, count: usize) -> io::Result<()> {
+            self.0.push(format!("{count} selected"));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn slices_and_readers_agree() {
+        let finder = Finder::new(b"duct");
+        let input = b"duct\nproduct, duct\r\nnothing\n\nlast duct";
+        let searchers = [
+            Searcher::new(),
+            Searcher::new().invert_match(true),
+            Searcher::new().context(true),
+        ];
+        for searcher in searchers {
+            let (mut streamed, mut sliced) = (Log::default(), Log::default());
+            searcher
+                .search_reader(&finder, &input[..], &mut streamed)
+                .unwrap();
+            searcher.search_slice(&finder, input, &mut sliced).unwrap();
+            assert_eq!(streamed.0, sliced.0);
+        }
+        let mut log = Log::default();
+        Searcher::new()
+            .search_slice(&finder, input, &mut log)
+            .unwrap();
+        let expected = [
+            "1@0: duct [0..4]",
+            "2@5: product, duct [3..7, 9..13]",
+            "5@29: last duct [5..9]",
+            "3 selected",
+        ];
+        assert_eq!(expected, log.0.as_slice());
+    }
+
+    #[test]
+    fn binary_input_goes_to_binary() {
+        let finder = Finder::new(b"duct");
+        let input = b"duct\n\0\nduct\n";
+        let mut log = Log::default();
+        Searcher::new()
+            .search_slice(&finder, input, &mut log)
+            .unwrap();
+        assert_eq!(["binary at 1", "1 selected"], log.0.as_slice());
+        let mut log = Log::default();
+        Searcher::new()
+            .text(true)
+            .search_slice(&finder, input, &mut log)
+            .unwrap();
+        assert_eq!(3, log.0.len());
+    }
+}
@@ -0,0 +1,579 @@
+// The --interactive terminal UI: a query line on top and the matching lines
+// below it, searched again whenever the query changes.
+//
+// The terminal is put in raw mode through termios, so every key arrives as
+// it is pressed, and the UI is drawn on the alternate screen, which leaves
+// the shell's scrollback untouched. The files are listed once at the start
+// and then searched with the same `Pattern` and `Searcher` as the command
+// line. Enter opens the selected line in $VISUAL or $EDITOR.
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufReader, IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{Config, GrepError, Outcome, Pattern, Searcher, Walk, gzip};
+
+// Searching stops after this many lines, so a short query in a large tree
+// still answers quickly.
+const MAX_HITS: usize = 1000;
+
+// The query and the status line come before the results.
+const HEADER_ROWS: usize = 2;
+
+const PROMPT: &str = "> ";
+
+// One matching line. `span` is the first match, in bytes of `line`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Hit {
+    path: PathBuf,
+    line_number: usize,
+    line: String,
+    span: std::ops::Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    Backspace,
+    // Ctrl-U, as in a shell
+    ClearQuery,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Enter,
+    Quit,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    query: String,
+    hits: Vec<Hit>,
+    // whether the search stopped at MAX_HITS
+    truncated: bool,
+    // why the query could not be searched for, such as a bad regex
+    error: Option<String>,
+    selected: usize,
+    // the index of the first hit on screen
+    scroll: usize,
+}
+
+pub fn run(config: &Config) -> Result<Outcome, GrepError> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err(GrepError::Usage(String::from(
+            "--interactive needs a terminal",
+        )));
+    }
+    let mut had_errors = false;
+    let mut files = Vec::new();
+    for file_path in &config.file_paths {
+        if Path::new(file_path).is_dir() {
+            let walk = Walk::new(file_path)
+                .hidden(config.hidden)
+                .include(&config.include)
+                .exclude(&config.exclude);
+            for entry in walk {
+                match entry {
+                    Ok(path) => files.push(path),
+                    // reported before the screen is taken over
+                    Err(e) => {
+                        eprintln!("mini-grep: {e}");
+                        had_errors = true;
+                    }
+                }
+            }
+        } else {
+            files.push(PathBuf::from(file_path));
+        }
+    }
+
+    let mut state = State {
+        query: config.patterns.first().cloned().unwrap_or_default(),
+        ..State::default()
+    };
+    state.search(config, &files);
+    interact(config, &files, &mut state).map_err(|e| GrepError::io("(terminal)", e))?;
+    Ok(Outcome {
+        matched: !state.hits.is_empty(),
+        had_errors,
+    })
+}
+
+// Reads keys and redraws until the user quits.
+fn interact(config: &Config, files: &[PathBuf], state: &mut State) -> io::Result<()> {
+    let mut terminal = sys::Terminal::enter()?;
+    let mut stdin = io::stdin().lock();
+    let mut buf = [0; 64];
+    loop {
+        let (rows, cols) = sys::window_size();
+        let height = rows.saturating_sub(HEADER_ROWS).max(1);
+        state.scroll_to_selection(height);
+        terminal.draw(&state.render(rows, cols))?;
+
+        let read = stdin.read(&mut buf)?;
+        let old_query = state.query.clone();
+        // everything typed while the last search ran is handled at once
+        for key in parse_keys(&buf[..read]) {
+            match key {
+                Key::Char(c) => state.query.push(c),
+                Key::Backspace => {
+                    state.query.pop();
+                }
+                Key::ClearQuery => state.query.clear(),
+                Key::Up => state.move_selection(-1),
+                Key::Down => state.move_selection(1),
+                Key::PageUp => state.move_selection(-(height as isize)),
+                Key::PageDown => state.move_selection(height as isize),
+                Key::Enter => {
+                    if let Some(hit) = state.hits.get(state.selected) {
+                        terminal.leave()?;
+                        let edited = open_in_editor(hit);
+                        terminal = sys::Terminal::enter()?;
+                        // shown until the next search
+                        state.error = edited.err();
+                    }
+                }
+                Key::Quit => return terminal.leave(),
+            }
+        }
+        if state.query != old_query {
+            state.search(config, files);
+        }
+    }
+}
+
+impl State {
+    // Replaces the hits with those of the current query.
+    fn search(&mut self, config: &Config, files: &[PathBuf]) {
+        self.hits.clear();
+        self.truncated = false;
+        self.error = None;
+        self.selected = 0;
+        self.scroll = 0;
+        if self.query.is_empty() {
+            return;
+        }
+        let config = Config {
+            patterns: vec![self.query.clone()],
+            ..config.clone()
+        };
+        let pattern = match Pattern::new(&config) {
+            Ok(pattern) => pattern,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return;
+            }
+        };
+        // only matching lines are listed, so context is never needed
+        let searcher = Searcher::from(&config).context(false);
+        for path in files {
+            let mut sink = |m: &crate::Match, _: &[std::ops::Range<usize>]| {
+                let line = m.line.split('\n').next().unwrap_or_default();
+                self.hits.push(Hit {
+                    path: path.clone(),
+                    line_number: m.line_number,
+                    line: line.to_string(),
+                    span: m.span.start.min(line.len())..m.span.end.min(line.len()),
+                });
+                Ok(self.hits.len() < MAX_HITS)
+            };
+            // files that cannot be read are left out of the list
+            let _ = File::open(path).and_then(|file| {
+                if config.decompress {
+                    let reader = gzip::maybe_decompress(true, BufReader::new(file))?;
+                    return searcher.search_reader(&pattern, reader, &mut sink);
+                }
+                searcher.search_file(&pattern, &file, &mut sink)
+            });
+            if self.hits.len() >= MAX_HITS {
+                self.truncated = true;
+                break;
+            }
+        }
+    }
+
+    fn move_selection(&mut self, by: isize) {
+        let last = self.hits.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(by).min(last);
+    }
+
+    // Scrolls just enough for the selected hit to be on screen.
+    fn scroll_to_selection(&mut self, height: usize) {
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + height {
+            self.scroll = self.selected + 1 - height;
+        }
+    }
+
+    // The whole screen, drawn from the top left corner, with the cursor left
+    // at the end of the query.
+    fn render(&self, rows: usize, cols: usize) -> String {
+        let mut screen = String::from("\x1b[?25l\x1b[H");
+        let _ = write!(screen, "{PROMPT}{}\x1b[K\r\n", fit(&self.query, cols - 2));
+        let status = match &self.error {
+            Some(error) => format!("\x1b[31m{}\x1b[0m", fit(error, cols)),
+            None => {
+                let more = if self.truncated { "+" } else { "" };
+                let status = format!(
+                    "{}{more} matches  (Up/Down select, Enter opens, Esc quits)",
+                    self.hits.len()
+                );
+                format!("\x1b[2m{}\x1b[0m", fit(&status, cols))
+            }
+        };
+        let _ = write!(screen, "{status}\x1b[K");
+        let height = rows.saturating_sub(HEADER_ROWS);
+        for i in self.scroll..self.scroll + height {
+            screen.push_str("\r\n");
+            if let Some(hit) = self.hits.get(i) {
+                render_hit(&mut screen, hit, i == self.selected, cols);
+            }
+            screen.push_str("\x1b[K");
+        }
+        let column = PROMPT.len() + fit(&self.query, cols - 2).chars().count() + 1;
+        let _ = write!(screen, "\x1b[1;{column}H\x1b[?25h");
+        screen
+    }
+}
+
+// One result row as "path:line:text", cut to `cols` characters. The selected
+// row is shown in reverse video, the others with their match in red.
+fn render_hit(screen: &mut String, hit: &Hit, selected: bool, cols: usize) {
+    let prefix = printable(&format!("{}:{}:", hit.path.display(), hit.line_number));
+    let line = printable(&hit.line);
+    if selected {
+        let row = format!("{prefix}{line}");
+        let _ = write!(screen, "\x1b[7m{}\x1b[0m", fit(&row, cols));
+        return;
+    }
+    let mut left = cols;
+    let parts = [
+        ("\x1b[35m", &prefix[..]),
+        ("", &line[..hit.span.start]),
+        ("\x1b[1;31m", &line[hit.span.clone()]),
+        ("", &line[hit.span.end..]),
+    ];
+    for (color, text) in parts {
+        let text = fit(text, left);
+        left -= text.chars().count();
+        if color.is_empty() {
+            screen.push_str(text);
+        } else {
+            let _ = write!(screen, "{color}{text}\x1b[0m");
+        }
+    }
+}
+
+// `text` with its control characters, such as tabs or the ESC and NUL of a
+// binary file, made spaces so the terminal does not act on them. Each gets
+// one space per byte, so the match span still fits.
+fn printable(text: &str) -> String {
+    let mut clean = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_control() {
+            clean.extend(std::iter::repeat_n(' ', c.len_utf8()));
+        } else {
+            clean.push(c);
+        }
+    }
+    clean
+}
+
+// The start of `text` that fits in `cols` columns, counting one per char.
+fn fit(text: &str, cols: usize) -> &str {
+    match text.char_indices().nth(cols) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+// The keys in what one read from the terminal returned. Escape sequences
+// for the arrow and page keys arrive whole; a lone Esc quits.
+fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let text = String::from_utf8_lossy(bytes);
+    let mut rest = &text[..];
+    while let Some(c) = rest.chars().next() {
+        let sequences = [
+            ("\x1b[A", Key::Up),
+            ("\x1bOA", Key::Up),
+            ("\x1b[B", Key::Down),
+            ("\x1bOB", Key::Down),
+            ("\x1b[5~", Key::PageUp),
+            ("\x1b[6~", Key::PageDown),
+        ];
+        if let Some((seq, key)) = sequences.iter().find(|(seq, _)| rest.starts_with(seq)) {
+            keys.push(*key);
+            rest = &rest[seq.len()..];
+            continue;
+        }
+        rest = &rest[c.len_utf8()..];
+        let key = match c {
+            '\r' | '\n' => Key::Enter,
+            '\x7f' | '\x08' => Key::Backspace,
+            '\x15' => Key::ClearQuery,
+            '\x10' => Key::Up,
+            '\x0e' => Key::Down,
+            // Ctrl-C, Ctrl-D, Esc
+            '\x03' | '\x04' => Key::Quit,
+            '\x1b' => {
+                // an escape sequence for some other key: skip it
+                if rest.starts_with(['[', 'O']) {
+                    let end = rest[1..]
+                        .find(|c: char| c.is_ascii_alphabetic() || c == '~')
+                        .map_or(rest.len(), |i| i + 2);
+                    rest = &rest[end..];
+                    continue;
+                }
+                Key::Quit
+            }
+            c if c.is_control() => continue,
+            c => Key::Char(c),
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+// Runs the editor on the hit's file at its line and waits for it. Most
+// editors (vi, vim, nano, emacs, micro) take the line as `+N`.
+fn open_in_editor(hit: &Hit) -> Result<(), String> {
+    // an empty variable is as good as unset
+    let set = |name| {
+        std::env::var(name)
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+    };
+    let editor = set("VISUAL").or_else(|| set("EDITOR")).unwrap_or_default();
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    Command::new(program)
+        .args(words)
+        .arg(format!("+{}", hit.line_number))
+        .arg(&hit.path)
+        .status()
+        .map_err(|e| format!("{program}: {e}"))?;
+    Ok(())
+}
+
+// Raw mode and the window size, from the C library like mmap(2) is.
+#[cfg(unix)]
+mod sys {
+    use std::ffi::{c_int, c_ulong};
+    use std::io::{self, Write};
+
+    const STDIN: c_int = 0;
+    const STDOUT: c_int = 1;
+    const TCSANOW: c_int = 0;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    const TIOCGWINSZ: c_ulong = 0x5413;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    const TIOCGWINSZ: c_ulong = 0x4008_7468;
+
+    // struct termios differs between systems, but it is only ever filled in
+    // and read by the C library, so room enough for any of them will do.
+    #[repr(C, align(8))]
+    #[derive(Clone, Copy)]
+    struct Termios([u8; 256]);
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Winsize {
+        rows: u16,
+        cols: u16,
+        x_pixels: u16,
+        y_pixels: u16,
+    }
+
+    unsafe extern "C" {
+        fn tcgetattr(fd: c_int, termios: *mut Termios) -> c_int;
+        fn tcsetattr(fd: c_int, action: c_int, termios: *const Termios) -> c_int;
+        fn cfmakeraw(termios: *mut Termios);
+        fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    }
+
+    // The terminal in raw mode on the alternate screen, until `leave` or
+    // drop puts it back the way it was.
+    pub struct Terminal {
+        saved: Option<Termios>,
+    }
+
+    impl Terminal {
+        pub fn enter() -> io::Result<Terminal> {
+            let mut saved = Termios([0; 256]);
+            // SAFETY: `saved` has room for the C library's struct termios
+            if unsafe { tcgetattr(STDIN, &mut saved) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut raw = saved;
+            // SAFETY: as above, on a struct tcgetattr filled in
+            if unsafe {
+                cfmakeraw(&mut raw);
+                tcsetattr(STDIN, TCSANOW, &raw)
+            } != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            let terminal = Terminal { saved: Some(saved) };
+            // the alternate screen, cleared
+            let mut out = io::stdout().lock();
+            out.write_all(b"\x1b[?1049h\x1b[2J")?;
+            out.flush()?;
+            Ok(terminal)
+        }
+
+        pub fn draw(&mut self, screen: &str) -> io::Result<()> {
+            let mut out = io::stdout().lock();
+            out.write_all(screen.as_bytes())?;
+            out.flush()
+        }
+
+        pub fn leave(&mut self) -> io::Result<()> {
+            let Some(saved) = self.saved.take() else {
+                return Ok(());
+            };
+            let mut out = io::stdout().lock();
+            out.write_all(b"\x1b[?1049l")?;
+            out.flush()?;
+            // SAFETY: restores what `enter` read
+            if unsafe { tcsetattr(STDIN, TCSANOW, &saved) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for Terminal {
+        fn drop(&mut self) {
+            let _ = self.leave();
+        }
+    }
+
+    // Rows and columns of the terminal, or 24 by 80 if it will not say.
+    pub fn window_size() -> (usize, usize) {
+        let mut size = Winsize::default();
+        // SAFETY: TIOCGWINSZ fills in exactly a struct winsize
+        let ok = unsafe { ioctl(STDOUT, TIOCGWINSZ, &mut size as *mut Winsize) } == 0;
+        if ok && size.rows > 0 && size.cols > 2 {
+            (size.rows.into(), size.cols.into())
+        } else {
+            (24, 80)
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod sys {
+    use std::io;
+
+    pub struct Terminal;
+
+    impl Terminal {
+        pub fn enter() -> io::Result<Terminal> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "--interactive needs a Unix terminal",
+            ))
+        }
+
+        pub fn draw(&mut self, _screen: &str) -> io::Result<()> {
+            Ok(())
+        }
+
+        pub fn leave(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    pub fn window_size() -> (usize, usize) {
+        (24, 80)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(line_number: usize) -> Hit {
+        Hit {
+            path: PathBuf::from("src/lib.rs"),
+            line_number,
+            line: String::from("fn main() {}"),
+            span: 3..7,
+        }
+    }
+
+    #[test]
+    fn keys_and_escape_sequences() {
+        assert_eq!(
+            vec![Key::Char('f'), Key::Char('ö'), Key::Backspace, Key::Enter],
+            parse_keys("fö\x7f\r".as_bytes())
+        );
+        assert_eq!(
+            vec![Key::Up, Key::Down, Key::PageDown, Key::Up],
+            parse_keys(b"\x1b[A\x1bOB\x1b[6~\x10")
+        );
+        // F5 is skipped, a lone Esc quits
+        assert_eq!(
+            vec![Key::Char('a'), Key::Quit],
+            parse_keys(b"\x1b[15~a\x1b")
+        );
+        assert_eq!(vec![Key::ClearQuery, Key::Quit], parse_keys(b"\x15\x03"));
+    }
+
+    #[test]
+    fn selection_stays_on_screen() {
+        let mut state = State {
+            hits: (1..=10).map(hit).collect(),
+            ..State::default()
+        };
+        state.move_selection(-1);
+        assert_eq!(0, state.selected);
+        state.move_selection(4);
+        state.scroll_to_selection(3);
+        assert_eq!((4, 2), (state.selected, state.scroll));
+        state.move_selection(100);
+        state.scroll_to_selection(3);
+        assert_eq!((9, 7), (state.selected, state.scroll));
+        state.move_selection(-8);
+        state.scroll_to_selection(3);
+        assert_eq!((1, 1), (state.selected, state.scroll));
+    }
+
+    #[test]
+    fn rows_fit_the_terminal() {
+        let state = State {
+            query: String::from("main"),
+            hits: vec![hit(1), hit(2)],
+            ..State::default()
+        };
+        let screen = state.render(4, 12);
+        // the cursor goes back to the end of the query
+        let screen = screen.strip_suffix("\x1b[1;7H\x1b[?25h").unwrap();
+        let rows: Vec<&str> = screen.split("\r\n").collect();
+        assert_eq!(4, rows.len());
+        assert!(rows[0].ends_with("> main\x1b[K"), "{:?}", rows[0]);
+        assert_eq!("\x1b[7msrc/lib.rs:1\x1b[0m\x1b[K", rows[2]);
+        assert_eq!(
+            "\x1b[35msrc/lib.rs:2\x1b[0m\x1b[K",
+            rows[3].replace("\x1b[1;31m\x1b[0m", "")
+        );
+    }
+
+    #[test]
+    fn control_characters_are_not_sent_to_the_terminal() {
+        let hit = Hit {
+            line: String::from("\x1b[2J\x07ma\u{85}in\0"),
+            span: 5..11,
+            ..hit(1)
+        };
+        let mut screen = String::new();
+        render_hit(&mut screen, &hit, false, 80);
+        assert_eq!(
+            "\x1b[35msrc/lib.rs:1:\x1b[0m [2J \x1b[1;31mma  in\x1b[0m ",
+            screen
+        );
+    }
+}
@@ -0,0 +1,246 @@
+// Recursive directory traversal for searching whole source trees.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::glob::Glob;
+use crate::ignore::Ignore;
+
+// Depth-first iterator over every regular file below a root path. Entries of
+// each directory are visited in sorted order so output is deterministic.
+// Symbolic links are not followed, which also keeps cycles out of the walk.
+//
+// Like ripgrep, the walk skips hidden files and directories (unless
+// `hidden` is set) and anything matched by the `.gitignore` and `.ignore`
+// files found on the way down. The root itself is never filtered.
+pub struct Walk {
+    root: PathBuf,
+    stack: Vec<Entry>,
+    hidden: bool,
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+}
+
+struct Entry {
+    path: PathBuf,
+    // the ignore rules of the directories above this entry
+    rules: Option<Rc<Rules>>,
+}
+
+// The ignore files of one directory, linked to those of its ancestors.
+struct Rules {
+    dir: PathBuf,
+    ignore: Ignore,
+    parent: Option<Rc<Rules>>,
+}
+
+impl Walk {
+    pub fn new(root: impl AsRef<Path>) -> Walk {
+        let root = root.as_ref().to_path_buf();
+        Walk {
+            stack: vec![Entry {
+                path: root.clone(),
+                rules: None,
+            }],
+            root,
+            hidden: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+
+    // Also walks files and directories whose names start with '.'.
+    pub fn hidden(mut self, yes: bool) -> Walk {
+        self.hidden = yes;
+        self
+    }
+
+    // Only yields files matching one of `globs`, when any are given.
+    pub fn include<S: AsRef<str>>(mut self, globs: &[S]) -> Walk {
+        self.include = globs.iter().map(|g| Glob::new(g.as_ref())).collect();
+        self
+    }
+
+    // Skips files and directories matching any of `globs`.
+    pub fn exclude<S: AsRef<str>>(mut self, globs: &[S]) -> Walk {
+        self.exclude = globs.iter().map(|g| Glob::new(g.as_ref())).collect();
+        self
+    }
+
+    fn read_dir(&mut self, dir: &Path, rules: Option<Rc<Rules>>) -> io::Result<()> {
+        let ignore = Ignore::from_dir(dir)?;
+        let rules = if ignore.is_empty() {
+            rules
+        } else {
+            Some(Rc::new(Rules {
+                dir: dir.to_path_buf(),
+                ignore,
+                parent: rules,
+            }))
+        };
+        let mut entries = fs::read_dir(dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        // Reverse so that popping from the stack yields sorted order.
+        entries.sort_unstable_by(|a, b| b.cmp(a));
+        self.stack.extend(entries.into_iter().map(|path| Entry {
+            path,
+            rules: rules.clone(),
+        }));
+        Ok(())
+    }
+
+    fn is_filtered(&self, entry: &Entry, is_dir: bool) -> bool {
+        let name = entry.path.file_name().unwrap_or_default().to_string_lossy();
+        // git's own data is never worth searching, even with `hidden`
+        if (name.starts_with('.') && !self.hidden) || (is_dir && name == ".git") {
+            return true;
+        }
+        // the nearest ignore file with a matching rule decides
+        let mut rules = entry.rules.as_deref();
+        while let Some(r) = rules {
+            if let Some(ignored) = r.ignore.matched(&relative(&entry.path, &r.dir), is_dir) {
+                if ignored {
+                    return true;
+                }
+                break;
+            }
+            rules = r.parent.as_deref();
+        }
+        // command-line globs apply to the name, or to the path below the
+        // root when they contain a '/'
+        let path = relative(&entry.path, &self.root);
+        let matches = |glob: &Glob| {
+            if glob.as_str().contains('/') {
+                glob.is_match(&path)
+            } else {
+                glob.is_match(&name)
+            }
+        };
+        if self.exclude.iter().any(matches) {
+            return true;
+        }
+        !is_dir && !self.include.is_empty() && !self.include.iter().any(matches)
+    }
+}
+
+impl Iterator for Walk {
+    // Errors carry the offending path in their message; the walk carries on
+    // with the next entry after reporting one.
+    type Item = io::Result<PathBuf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(entry) = self.stack.pop() {
+            let file_type = match fs::symlink_metadata(&entry.path) {
+                Ok(meta) => meta.file_type(),
+                Err(e) => return Some(Err(with_path(&entry.path, e))),
+            };
+            if entry.path != self.root && self.is_filtered(&entry, file_type.is_dir()) {
+                continue;
+            }
+            if file_type.is_dir() {
+                if let Err(e) = self.read_dir(&entry.path, entry.rules) {
+                    return Some(Err(with_path(&entry.path, e)));
+                }
+            } else if file_type.is_file() {
+                return Some(Ok(entry.path));
+            }
+        }
+        None
+    }
+}
+
+// `path` below `dir`, with '/' between components.
+fn relative(path: &Path, dir: &Path) -> String {
+    let rel = path.strip_prefix(dir).unwrap_or(path);
+    let parts: Vec<_> = rel.iter().map(|part| part.to_string_lossy()).collect();
+    parts.join("/")
+}
+
+fn with_path(path: &Path, err: io::Error) -> io::Error {
+    io::Error::new(err.kind(), format!("{}: {err}", path.display()))
+}
+
+// A file is treated as binary when its first block contains a NUL byte,
+// the same heuristic grep uses.
+pub fn is_binary(contents: &[u8]) -> bool {
+    contents.iter().take(8192).any(|&b| b == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempdir::TempDir;
+
+    #[test]
+    fn walks_nested_directories_in_order() {
+        let root = TempDir::new("walk");
+        fs::create_dir_all(root.join("b/nested")).unwrap();
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::write(root.join("b/nested/deep.txt"), "deep").unwrap();
+        fs::write(root.join("b/top.txt"), "top").unwrap();
+        fs::write(root.join("a/first.txt"), "first").unwrap();
+
+        let found: Vec<PathBuf> = Walk::new(&root).map(|p| p.unwrap()).collect();
+        assert_eq!(
+            vec![
+                root.join("a/first.txt"),
+                root.join("b/nested/deep.txt"),
+                root.join("b/top.txt"),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn ignored_hidden_and_excluded_entries_are_skipped() {
+        let root = TempDir::new("ignore");
+        for dir in ["target/debug", "src/gen", ".hidden"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        let files = [
+            ".gitignore",
+            "target/debug/out.txt",
+            "src/main.rs",
+            "src/notes.log",
+            "src/keep.log",
+            "src/gen/code.rs",
+            ".hidden/secret.rs",
+        ];
+        for file in files {
+            fs::write(root.join(file), "x").unwrap();
+        }
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(root.join("src/.ignore"), "!keep.log\n").unwrap();
+
+        let walk =
+            |walk: Walk| -> Vec<String> { walk.map(|p| relative(&p.unwrap(), &root)).collect() };
+        assert_eq!(
+            vec!["src/gen/code.rs", "src/keep.log", "src/main.rs"],
+            walk(Walk::new(&root))
+        );
+        assert_eq!(
+            vec![".hidden/secret.rs", "src/.ignore", "src/main.rs"],
+            walk(
+                Walk::new(&root)
+                    .hidden(true)
+                    .include(&["*.rs", "*.ignore"])
+                    .exclude(&["src/gen"])
+            )
+        );
+    }
+
+    #[test]
+    fn missing_root_is_reported() {
+        let mut walk = Walk::new("definitely/not/here");
+        assert!(walk.next().unwrap().is_err());
+        assert!(walk.next().is_none());
+    }
+
+    #[test]
+    fn nul_bytes_mean_binary() {
+        assert!(is_binary(b"ELF\0\x01"));
+        assert!(!is_binary("plain text\n".as_bytes()));
+    }
+}
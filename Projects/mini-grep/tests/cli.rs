@@ -0,0 +1,38 @@
+// Runs the mini-grep binary itself, for behavior that depends on how its
+// input and output are connected.
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn stdin_is_searched_as_it_arrives() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mini-grep"))
+        .arg("foo")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            tx.send(line.unwrap()).unwrap();
+        }
+    });
+
+    stdin.write_all(b"foo\nbar\n").unwrap();
+    stdin.flush().unwrap();
+    // the first match is printed while stdin is still open
+    let first = rx.recv_timeout(Duration::from_secs(10));
+    stdin.write_all(b"bar foo\n").unwrap();
+    drop(stdin);
+    assert_eq!(Ok(String::from("foo")), first);
+    assert_eq!(
+        Ok(String::from("bar foo")),
+        rx.recv_timeout(Duration::from_secs(10))
+    );
+    assert!(child.wait().unwrap().success());
+}